@@ -18,20 +18,23 @@ use tui::symbols::DOT;
 use tui::widgets::{Block, BorderType, Borders, Tabs, Widget};
 use tui::Terminal;
 
-use mct::mudnet::{self, CnxOutput};
+use mct::config::Config;
 use mct::ui;
 use mct::ui::app::App;
 use mct::ui::app_events;
 use mct::ui::events::{Event, Events};
+use mudnet::{self, CnxInput, CnxOutput};
 use std::fs::read;
 
 #[tokio::main]
 async fn main() -> Result<(), failure::Error> {
     log4rs::init_file("log4rs.yml", Default::default()).unwrap();
 
-    let mut app = App::new();
+    let config = Config::load();
+    let mud_config = config.mud_config();
+    let mut app = App::new(config);
 
-    let (mut command_sender, mut command_receiver): (Sender<String>, Receiver<String>) =
+    let (mut command_sender, mut command_receiver): (Sender<CnxInput>, Receiver<CnxInput>) =
         mpsc::channel(100);
     let (mut cnx_sender, mut cnx_receiver): (Sender<CnxOutput>, Receiver<CnxOutput>) =
         mpsc::channel(100);
@@ -49,7 +52,12 @@ async fn main() -> Result<(), failure::Error> {
         },
     ));
 
-    tokio::spawn(mudnet::handler(tcp_stream, command_receiver, cnx_sender));
+    tokio::spawn(mudnet::handler(
+        tcp_stream,
+        mud_config,
+        command_receiver,
+        cnx_sender,
+    ));
 
     tokio::spawn(async move {
         loop {
@@ -61,6 +69,9 @@ async fn main() -> Result<(), failure::Error> {
                 Ok(CnxOutput::Msdp(data)) => {
                     println!("receive msdp: {:?}", data);
                 }
+                Ok(other) => {
+                    println!("receive: {:?}", other);
+                }
                 Err(TryRecvError::Empty) => {
                     //debug!("try receive cnx empty !");
                     ()
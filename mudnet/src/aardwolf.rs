@@ -0,0 +1,45 @@
+/*
+    Aardwolf's 102 channel:
+
+    https://wiki.mudlet.org/w/Manual:Supported_Protocols#Aardwolf.E2.80.99s_102_subchannel
+
+    IAC SB 102 <tag> <value> IAC SE
+*/
+use std::io;
+
+pub const STATUS: u8 = 100;
+pub const TICK: u8 = 101;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Aard102 {
+    /// Player state, e.g. 1 at the login screen, 3 fully active, 4 AFK, 8 in combat ...
+    Status(u8),
+    Tick,
+    Tag(u8, u8),
+}
+
+pub fn parse_102(data: &[u8]) -> io::Result<Aard102> {
+    match data {
+        [STATUS, status] => Ok(Aard102::Status(*status)),
+        [TICK, _] => Ok(Aard102::Tick),
+        [tag, value] => Ok(Aard102::Tag(*tag, *value)),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("expected two bytes, found {:?}", data),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn two_bytes_messages() -> io::Result<()> {
+        assert_eq!(parse_102(&[100, 3])?, Aard102::Status(3));
+        assert_eq!(parse_102(&[101, 1])?, Aard102::Tick);
+        assert_eq!(parse_102(&[5, 1])?, Aard102::Tag(5, 1));
+        assert!(parse_102(&[100]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,94 @@
+/*
+    Achaea Telnet Client Protocol:
+
+    https://www.ironrealms.com/rapture/manual/files/FeatATCP-txt.html
+
+    IAC SB ATCP "Module.Message value" IAC SE
+*/
+use std::io;
+use telnet::{TelnetOption, TelnetWriter};
+
+use super::MudConfig;
+use crate::mud::options::ATCP;
+
+/// Modules enabled by the `hello` message
+const MODULES: [&'static str; 6] = [
+    "auth 1",
+    "char_name 1",
+    "char_vitals 1",
+    "room_brief 1",
+    "room_exits 1",
+    "composer 1",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AtcpMessage {
+    pub module: String,
+    pub message: String,
+    pub value: String,
+}
+
+/// Introduces the client and asks for the modules we know about
+pub async fn hello(telnet: &mut TelnetWriter<'_>, config: &MudConfig) -> io::Result<()> {
+    let msg = format!("hello {}\n{}", config.client_name, MODULES.join("\n"));
+
+    telnet
+        .try_subnegotiate(TelnetOption::UnknownOption(ATCP), &[msg.as_bytes()])
+        .await?;
+
+    Ok(())
+}
+
+pub fn parse_atcp(data: &[u8]) -> io::Result<AtcpMessage> {
+    let data = std::str::from_utf8(data)
+        .map_err(|e| -> io::Error { io::Error::new(io::ErrorKind::InvalidInput, e.to_string()) })?;
+
+    let (name, value) = match data.find(|c: char| c == ' ' || c == '\n') {
+        Some(i) => (&data[..i], &data[i + 1..]),
+        None => (data, ""),
+    };
+
+    let mut parts = name.splitn(2, '.');
+
+    match (parts.next(), parts.next()) {
+        (Some(module), Some(message)) if !module.is_empty() && !message.is_empty() => {
+            Ok(AtcpMessage {
+                module: String::from(module),
+                message: String::from(message),
+                value: String::from(value),
+            })
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("expected Module.Message, found {:?}", name),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message_with_value() -> io::Result<()> {
+        let msg = parse_atcp(b"Char.Vitals H:100/120 M:90/90")?;
+        assert_eq!(msg.module, "Char");
+        assert_eq!(msg.message, "Vitals");
+        assert_eq!(msg.value, "H:100/120 M:90/90");
+        Ok(())
+    }
+
+    #[test]
+    fn multi_line_value() -> io::Result<()> {
+        let msg = parse_atcp(b"Room.Exits\nn,s,e")?;
+        assert_eq!(msg.module, "Room");
+        assert_eq!(msg.message, "Exits");
+        assert_eq!(msg.value, "n,s,e");
+        Ok(())
+    }
+
+    #[test]
+    fn message_without_module() {
+        assert!(parse_atcp(b"Vitals 100").is_err());
+    }
+}
@@ -0,0 +1,133 @@
+/*
+    Outgoing data encoding:
+
+    https://tools.ietf.org/html/rfc854 (NVT, IAC escaping)
+    https://tools.ietf.org/html/rfc856 (TELNET BINARY)
+*/
+use std::io;
+
+pub const IAC: u8 = 255;
+
+const CR: u8 = b'\r';
+const LF: u8 = b'\n';
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Charset {
+    Utf8,
+    Latin1,
+}
+
+impl Charset {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Charset::Utf8 => "UTF-8",
+            Charset::Latin1 => "ISO-8859-1",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Charset> {
+        match name.to_uppercase().as_str() {
+            "UTF-8" | "UTF8" => Some(Charset::Utf8),
+            "ISO-8859-1" | "LATIN-1" | "LATIN1" => Some(Charset::Latin1),
+            _ => None,
+        }
+    }
+}
+
+pub fn encode(text: &str, charset: Charset) -> Vec<u8> {
+    match charset {
+        Charset::Utf8 => Vec::from(text.as_bytes()),
+        Charset::Latin1 => text
+            .chars()
+            .map(|c| if (c as u32) < 256 { c as u8 } else { b'?' })
+            .collect(),
+    }
+}
+
+pub fn decode(data: &[u8], charset: Charset) -> io::Result<String> {
+    match charset {
+        Charset::Utf8 => std::str::from_utf8(data)
+            .map(String::from)
+            .map_err(|e| -> io::Error { io::Error::new(io::ErrorKind::InvalidInput, e.to_string()) }),
+        Charset::Latin1 => Ok(data.iter().map(|b| *b as char).collect()),
+    }
+}
+
+fn strip_line_ending(line: &str) -> &str {
+    if line.ends_with("\r\n") {
+        &line[..line.len() - 2]
+    } else if line.ends_with('\n') || line.ends_with('\r') {
+        &line[..line.len() - 1]
+    } else {
+        line
+    }
+}
+
+/// Encodes a command line to be written on the socket, terminated by CRLF and with IAC doubled.
+/// Outside of binary mode, the line endings it contains are normalized to CRLF as well,
+/// in binary mode the bytes are sent as they are.
+pub fn encode_line(line: &str, charset: Charset, binary: bool) -> Vec<u8> {
+    let bytes = encode(strip_line_ending(line), charset);
+    let mut out: Vec<u8> = Vec::with_capacity(bytes.len() + 2);
+
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            IAC => {
+                out.push(IAC);
+                out.push(IAC);
+            }
+            CR if !binary => {
+                if bytes.get(i + 1) == Some(&LF) {
+                    i += 1;
+                }
+                out.push(CR);
+                out.push(LF);
+            }
+            LF if !binary => {
+                out.push(CR);
+                out.push(LF);
+            }
+            b => out.push(b),
+        }
+        i += 1;
+    }
+
+    out.push(CR);
+    out.push(LF);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn iac_is_doubled() {
+        assert_eq!(
+            encode_line("say \u{ff}", Charset::Latin1, false),
+            vec![b's', b'a', b'y', b' ', IAC, IAC, CR, LF]
+        );
+        assert_eq!(
+            encode_line("\u{ff}", Charset::Latin1, true),
+            vec![IAC, IAC, CR, LF]
+        );
+    }
+
+    #[test]
+    fn line_endings_are_normalized() {
+        assert_eq!(encode_line("look\n", Charset::Utf8, false), b"look\r\n");
+        assert_eq!(encode_line("look\r\n", Charset::Utf8, false), b"look\r\n");
+        assert_eq!(encode_line("n\ns\re", Charset::Utf8, false), b"n\r\ns\r\ne\r\n");
+        assert_eq!(encode_line("n\ns", Charset::Utf8, true), b"n\ns\r\n");
+    }
+
+    #[test]
+    fn latin1_round_trip() -> io::Result<()> {
+        let encoded = encode("café", Charset::Latin1);
+        assert_eq!(encoded, vec![b'c', b'a', b'f', 0xe9]);
+        assert_eq!(decode(&encoded, Charset::Latin1)?, "café");
+        assert_eq!(encode("€", Charset::Latin1), b"?");
+        Ok(())
+    }
+}
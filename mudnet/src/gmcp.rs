@@ -3,6 +3,13 @@ use std::io;
 use telnet::{TelnetOption, TelnetWriter};
 //client - IAC   SB GMCP 'MSDP {"LIST" : "COMMANDS"}' IAC SE
 
+/// `IAC SB GMCP "Package.Message <json>" IAC SE`, the json being kept as text
+#[derive(Debug, Clone, PartialEq)]
+pub struct GmcpMessage {
+    pub package: String,
+    pub data: String,
+}
+
 pub async fn list_command(telnet: &mut TelnetWriter<'_>) -> io::Result<()> {
     let msg = "MSDP {\"LIST\" : \"COMMANDS\"}";
 
@@ -12,3 +19,50 @@ pub async fn list_command(telnet: &mut TelnetWriter<'_>) -> io::Result<()> {
 
     Ok(())
 }
+
+/// Sends a message, e.g. `Core.Supports.Set ["Char 1"]`
+pub async fn send(telnet: &mut TelnetWriter<'_>, msg: &str) -> io::Result<()> {
+    telnet
+        .try_subnegotiate(TelnetOption::UnknownOption(GMCP), &[msg.as_bytes()])
+        .await?;
+
+    Ok(())
+}
+
+pub fn parse_gmcp(data: &[u8]) -> io::Result<GmcpMessage> {
+    let data = std::str::from_utf8(data)
+        .map_err(|e| -> io::Error { io::Error::new(io::ErrorKind::InvalidInput, e.to_string()) })?;
+
+    let (package, json) = match data.find(char::is_whitespace) {
+        Some(i) => (&data[..i], data[i..].trim()),
+        None => (data, ""),
+    };
+
+    if package.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "empty GMCP package",
+        ));
+    }
+    Ok(GmcpMessage {
+        package: String::from(package),
+        data: String::from(json),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message_with_data() -> io::Result<()> {
+        let msg = parse_gmcp(b"Char.Vitals { \"hp\": 100, \"maxhp\": 120 }")?;
+        assert_eq!(msg.package, "Char.Vitals");
+        assert_eq!(msg.data, "{ \"hp\": 100, \"maxhp\": 120 }");
+
+        let msg = parse_gmcp(b"Core.Goodbye")?;
+        assert_eq!(msg.package, "Core.Goodbye");
+        assert_eq!(msg.data, "");
+        Ok(())
+    }
+}
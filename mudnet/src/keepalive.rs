@@ -0,0 +1,120 @@
+/*
+    Keepalive and latency measurement:
+
+    https://tools.ietf.org/html/rfc860 (TIMING-MARK)
+
+    client - IAC DO TIMING-MARK
+    server - IAC WILL TIMING-MARK (or WONT), the delay is the round trip time
+
+    Servers never answering TIMING-MARK are kept alive with IAC NOP.
+*/
+use std::time::{Duration, Instant};
+
+pub const NOP: u8 = 241;
+
+// delay before the first probe, to get a latency soon after connecting
+const FIRST_PROBE_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Probe {
+    Nop,
+    TimingMark,
+}
+
+#[derive(Debug, Clone)]
+pub struct Keepalive {
+    interval: Duration,
+    next_probe: Instant,
+    /// when the unanswered TIMING-MARK was sent
+    pending: Option<Instant>,
+    /// unknown until the server answers, or ignores, a first TIMING-MARK
+    timing_mark: Option<bool>,
+    latency: Option<Duration>,
+}
+
+impl Keepalive {
+    pub fn new(interval: Duration, now: Instant) -> Keepalive {
+        Keepalive {
+            interval,
+            next_probe: now + FIRST_PROBE_DELAY.min(interval),
+            pending: None,
+            timing_mark: None,
+            latency: None,
+        }
+    }
+
+    /// Returns the probe to send if one is due
+    pub fn poll(&mut self, now: Instant) -> Option<Probe> {
+        if now < self.next_probe {
+            return None;
+        }
+        self.next_probe = now + self.interval;
+
+        if self.pending.take().is_some() && self.timing_mark.is_none() {
+            // no answer during a whole interval
+            self.timing_mark = Some(false);
+        }
+
+        if self.timing_mark == Some(false) {
+            Some(Probe::Nop)
+        } else {
+            self.pending = Some(now);
+            Some(Probe::TimingMark)
+        }
+    }
+
+    /// Handles a WILL or WONT TIMING-MARK, returns the round trip time if we were waiting for it
+    pub fn answer(&mut self, now: Instant) -> Option<Duration> {
+        let sent = self.pending.take()?;
+        let latency = now.duration_since(sent);
+        self.timing_mark = Some(true);
+        self.latency = Some(latency);
+        Some(latency)
+    }
+
+    pub fn latency(&self) -> Option<Duration> {
+        self.latency
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_latency() {
+        let start = Instant::now();
+        let mut keepalive = Keepalive::new(Duration::from_secs(60), start);
+
+        assert_eq!(keepalive.poll(start), None);
+
+        let sent = start + Duration::from_secs(5);
+        assert_eq!(keepalive.poll(sent), Some(Probe::TimingMark));
+        assert_eq!(
+            keepalive.answer(sent + Duration::from_millis(42)),
+            Some(Duration::from_millis(42))
+        );
+        assert_eq!(keepalive.latency(), Some(Duration::from_millis(42)));
+
+        // unsolicited answers are ignored
+        assert_eq!(keepalive.answer(sent + Duration::from_secs(1)), None);
+    }
+
+    #[test]
+    fn falls_back_to_nop() {
+        let start = Instant::now();
+        let mut keepalive = Keepalive::new(Duration::from_secs(60), start);
+
+        let first = start + Duration::from_secs(5);
+        assert_eq!(keepalive.poll(first), Some(Probe::TimingMark));
+        assert_eq!(
+            keepalive.poll(first + Duration::from_secs(60)),
+            Some(Probe::Nop)
+        );
+        assert_eq!(
+            keepalive.poll(first + Duration::from_secs(120)),
+            Some(Probe::Nop)
+        );
+        assert_eq!(keepalive.latency(), None);
+    }
+}
@@ -3,38 +3,106 @@ use im::hashmap::HashMap;
 use log::{debug, warn};
 use std::borrow::Borrow;
 use std::io;
+use std::time::{Duration, Instant};
 use telnet::{NegotiationAction, Telnet, TelnetEvent, TelnetOption, TelnetWriter};
+use tokio::net::TcpStream;
 use tokio::sync::mpsc::{self, error::TryRecvError, Receiver, Sender};
-use tokio::task;
+use tokio::{task, time};
 
+pub mod aardwolf;
+pub mod atcp;
+pub mod encoder;
 pub mod gmcp;
+pub mod keepalive;
 mod lexer;
-mod msdp;
-mod mtts;
+mod mnes;
+pub mod msdp;
+pub mod mslp;
+pub mod msp;
+pub mod mtts;
 pub mod mud;
 
+use aardwolf::Aard102;
+use atcp::AtcpMessage;
+use encoder::Charset;
+use gmcp::GmcpMessage;
+use keepalive::{Keepalive, Probe};
 use msdp::MsdpData;
+use mslp::{Link, MslpParser, Segment};
+use msp::{MspParser, Sound};
+
+/// Longest wait for the server to accept the connection
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+/// The text held back for an MSP trigger is shown when nothing follows it for that long
+const MSP_FLUSH_DELAY: Duration = Duration::from_millis(200);
 
 pub struct MudConfig {
     pub client_name: String,
     pub terminal_type: &'static str,
     pub features: mtts::Features,
+    pub charset: Charset,
+    /// negotiate TELNET BINARY
+    pub binary: bool,
+    /// interval between two keepalive probes, also used to measure the latency
+    pub keepalive: Option<Duration>,
+    /// strip MSP triggers from the text and emit them as `CnxOutput::Sound`
+    pub msp: bool,
+    /// split the MSLP links from the text and emit them as `CnxOutput::Link`
+    pub mslp: bool,
+    /// negotiate ATCP, used by the older Iron Realms games
+    pub atcp: bool,
+    /// negotiate Aardwolf's 102 channel
+    pub aardwolf102: bool,
+    /// negotiate MSDP, the list of the server commands is asked once it is active
+    pub msdp: bool,
 }
 
+const SUPPORTED_OPTIONS: [TelnetOption; 2] = [
+    TelnetOption::TTYPE,
+    TelnetOption::UnknownOption(mud::options::GMCP),
+];
+
 impl MudConfig {
     pub fn default() -> MudConfig {
         MudConfig {
             client_name: String::from("mudnet"),
             terminal_type: mtts::terminal_type::XTERM,
-            features: mtts::Features::ANSI | mtts::Features::UTF8,
+            features: mtts::Features::ANSI
+                | mtts::Features::UTF8
+                | mtts::Features::MNES
+                | mtts::Features::MSLP,
+            charset: Charset::Utf8,
+            binary: false,
+            keepalive: Some(Duration::from_secs(60)),
+            msp: true,
+            mslp: true,
+            atcp: false,
+            aardwolf102: false,
+            msdp: true,
         }
     }
+
+    pub fn supports(&self, option: &TelnetOption) -> bool {
+        SUPPORTED_OPTIONS.contains(option)
+            || match option {
+                TelnetOption::UnknownOption(mud::options::MSP) => self.msp,
+                TelnetOption::UnknownOption(mud::options::ATCP) => self.atcp,
+                TelnetOption::UnknownOption(mud::options::AARDWOLF102) => self.aardwolf102,
+                TelnetOption::UnknownOption(mud::options::MSDP) => self.msdp,
+                TelnetOption::TransmitBinary => self.binary,
+                TelnetOption::NewEnvironment => self.features.contains(mtts::Features::MNES),
+                _ => false,
+            }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct CnxState {
     negociated_options: HashMap<u8, NegotiationState>,
     mtts_num_call: u8,
+    keepalive: Option<Keepalive>,
+    /// the server echoes the input itself
+    server_echo: bool,
 }
 
 impl CnxState {
@@ -42,6 +110,8 @@ impl CnxState {
         CnxState {
             negociated_options: HashMap::new(),
             mtts_num_call: 0,
+            keepalive: None,
+            server_echo: false,
         }
     }
 
@@ -55,6 +125,20 @@ impl CnxState {
             None => NegotiationState::new(*option),
         }
     }
+
+    pub fn is_active(&self, option: &TelnetOption) -> bool {
+        self.negociated_options
+            .get(&option.to_byte())
+            .map_or(false, |state| state.is_active())
+    }
+
+    /// The options negotiated so far, by option code
+    pub fn options(&self) -> Vec<NegotiationState> {
+        let mut options: Vec<NegotiationState> =
+            self.negociated_options.values().cloned().collect();
+        options.sort_by_key(|state| state.option.to_byte());
+        options
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -95,11 +179,6 @@ pub struct NegotiationState {
     pub send_dont: bool,
 }
 
-const SUPPORTED_OPTIONS: [TelnetOption; 2] = [
-    TelnetOption::TTYPE,
-    TelnetOption::UnknownOption(mud::options::GMCP),
-];
-
 impl NegotiationState {
     fn new(option: TelnetOption) -> NegotiationState {
         NegotiationState {
@@ -115,14 +194,14 @@ impl NegotiationState {
         }
     }
 
-    fn shoud_negotiate(&self) -> bool {
-        SUPPORTED_OPTIONS.contains(&self.option)
+    fn shoud_negotiate(&self, config: &MudConfig) -> bool {
+        config.supports(&self.option)
             && !self.received_dont
             && !self.received_wont
             && !self.send_do
     }
 
-    fn is_active(&self) -> bool {
+    pub fn is_active(&self) -> bool {
         (self.received_will || self.received_do)
             && self.send_do
             && !self.received_wont
@@ -136,7 +215,7 @@ pub struct Chunk {
     pub data: String,
 }
 
-pub async fn read_chunk(telnet: &mut Telnet<'_>) -> Result<Chunk, io::Error> {
+pub async fn read_chunk(telnet: &mut Telnet<'_>, charset: Charset) -> Result<Chunk, io::Error> {
     let mut data = String::new();
     let mut negotiations: Vec<Negotiation> = Vec::new();
 
@@ -149,11 +228,10 @@ pub async fn read_chunk(telnet: &mut Telnet<'_>) -> Result<Chunk, io::Error> {
             negotiations.push(Negotiation::Subnegotiation(opt, negoData))
         }
         TelnetEvent::Data(buffer) => {
-            let d = std::str::from_utf8(buffer.borrow()).map_err(|e| -> io::Error {
-                io::Error::new(io::ErrorKind::InvalidInput, e.to_string())
-            })?;
-            data.push_str(d);
+            let d = encoder::decode(buffer.borrow(), charset)?;
+            data.push_str(&d);
         }
+        TelnetEvent::UnknownIAC(keepalive::NOP) => (),
         TelnetEvent::UnknownIAC(code) => {
             return Err(io::Error::new(
                 io::ErrorKind::InvalidInput,
@@ -190,10 +268,36 @@ async fn handle_sub_negotiations(
             mtts::handle_sub_negotiations(telnet, config, cnx_state).await;
             Ok(None)
         }
+        TelnetOption::NewEnvironment => {
+            debug!("handling sub negotiations for NEW-ENVIRON");
+            mnes::handle_sub_negotiations(telnet, config, data.borrow()).await?;
+            Ok(None)
+        }
         TelnetOption::UnknownOption(mud::options::MSDP) => {
             let msdp_data = msdp::parse_msdp(data.borrow())?;
             Ok(Some(CnxOutput::Msdp(msdp_data)))
         }
+        TelnetOption::UnknownOption(mud::options::GMCP) => {
+            let message = gmcp::parse_gmcp(data.borrow())?;
+            Ok(Some(CnxOutput::Gmcp(message)))
+        }
+        // a malformed message is dropped, keeping the connection
+        TelnetOption::UnknownOption(mud::options::ATCP) => match atcp::parse_atcp(data.borrow()) {
+            Ok(message) => Ok(Some(CnxOutput::Atcp(message))),
+            Err(e) => {
+                warn!("ignoring malformed ATCP message {:?} : {}", data, e);
+                Ok(None)
+            }
+        },
+        TelnetOption::UnknownOption(mud::options::AARDWOLF102) => {
+            match aardwolf::parse_102(data.borrow()) {
+                Ok(message) => Ok(Some(CnxOutput::Aardwolf(message))),
+                Err(e) => {
+                    warn!("ignoring malformed 102 message {:?} : {}", data, e);
+                    Ok(None)
+                }
+            }
+        }
         _ => {
             warn!("ignoring subnegotiation for {:?}", (opt, data));
             Err(io::Error::new(
@@ -204,6 +308,75 @@ async fn handle_sub_negotiations(
     }
 }
 
+async fn handle_timing_mark(
+    telnet: &mut TelnetWriter<'_>,
+    state: &mut CnxState,
+    action: &NegotiationAction,
+) -> io::Result<Option<CnxOutput>> {
+    match action {
+        NegotiationAction::Will | NegotiationAction::Wont => Ok(state
+            .keepalive
+            .as_mut()
+            .and_then(|k| k.answer(Instant::now()))
+            .map(CnxOutput::Latency)),
+        NegotiationAction::Do => {
+            telnet
+                .try_negotiate(NegotiationAction::Will, TelnetOption::TimingMark)
+                .await?;
+            Ok(None)
+        }
+        NegotiationAction::Dont => Ok(None),
+    }
+}
+
+/// WILL ECHO: the server echoes the input itself, which it does while a password is typed
+async fn handle_echo(
+    telnet: &mut TelnetWriter<'_>,
+    state: &mut CnxState,
+    action: &NegotiationAction,
+) -> io::Result<Option<CnxOutput>> {
+    match action {
+        NegotiationAction::Will | NegotiationAction::Wont => {
+            let echo = *action == NegotiationAction::Will;
+            // already in that mode, nothing to acknowledge
+            if echo == state.server_echo {
+                return Ok(None);
+            }
+            state.server_echo = echo;
+
+            let answer = if echo {
+                NegotiationAction::Do
+            } else {
+                NegotiationAction::Dont
+            };
+            telnet.try_negotiate(answer, TelnetOption::Echo).await?;
+            Ok(Some(CnxOutput::ServerEcho(echo)))
+        }
+        NegotiationAction::Do => {
+            telnet
+                .try_negotiate(NegotiationAction::Wont, TelnetOption::Echo)
+                .await?;
+            Ok(None)
+        }
+        NegotiationAction::Dont => Ok(None),
+    }
+}
+
+async fn send_probe(telnet: &mut TelnetWriter<'_>, probe: Probe) -> io::Result<()> {
+    debug!("sending keepalive {:?}", probe);
+    match probe {
+        Probe::Nop => {
+            telnet.write(&[encoder::IAC, keepalive::NOP]).await?;
+        }
+        Probe::TimingMark => {
+            telnet
+                .try_negotiate(NegotiationAction::Do, TelnetOption::TimingMark)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
 pub async fn handle_negotiation(
     telnet: &mut TelnetWriter<'_>,
     config: &MudConfig,
@@ -211,10 +384,26 @@ pub async fn handle_negotiation(
     n: &Negotiation,
 ) -> io::Result<Option<CnxOutput>> {
     match n {
+        Negotiation::Negotiation(action, TelnetOption::TimingMark) => {
+            handle_timing_mark(telnet, state, action).await
+        }
+        Negotiation::Negotiation(action, TelnetOption::Echo) => {
+            handle_echo(telnet, state, action).await
+        }
         Negotiation::Negotiation(action, opt)
             if *action == NegotiationAction::Do || *action == NegotiationAction::Will =>
         {
-            negotiate_answer(telnet, state, action, opt).await;
+            let was_active = state.is_active(opt);
+            negotiate_answer(telnet, config, state, action, opt).await?;
+
+            // once, a server may send both DO and WILL
+            let activated = !was_active && state.is_active(opt);
+            if activated && *opt == TelnetOption::UnknownOption(mud::options::ATCP) {
+                atcp::hello(telnet, config).await?;
+            }
+            if activated && *opt == TelnetOption::UnknownOption(mud::options::MSDP) {
+                msdp::list_commands(telnet).await?;
+            }
             Ok(None)
         }
         Negotiation::Subnegotiation(option, data) => {
@@ -244,23 +433,25 @@ pub async fn handle_negotiation(
 
 pub async fn negotiate(
     telnet: &mut TelnetWriter<'_>,
+    config: &MudConfig,
     state: &mut CnxState,
     opt: &TelnetOption,
 ) -> io::Result<()> {
     let mut nego_state = state.negotiation_state(opt);
-    do_negotiate(telnet, &mut nego_state).await?;
+    do_negotiate(telnet, config, &mut nego_state).await?;
     state.add_negociated_option(nego_state);
     Ok(())
 }
 
 pub async fn negotiate_answer(
     telnet: &mut TelnetWriter<'_>,
+    config: &MudConfig,
     state: &mut CnxState,
     action: &NegotiationAction,
     opt: &TelnetOption,
 ) -> io::Result<()> {
     let mut nego_state = state.negotiation_state(opt);
-    do_negotiate(telnet, &mut nego_state).await?;
+    do_negotiate(telnet, config, &mut nego_state).await?;
 
     nego_state.received_do = nego_state.received_do || *action == NegotiationAction::Do;
     nego_state.received_will = nego_state.received_will || *action == NegotiationAction::Will;
@@ -271,9 +462,10 @@ pub async fn negotiate_answer(
 
 async fn do_negotiate(
     telnet: &mut TelnetWriter<'_>,
+    config: &MudConfig,
     nego_state: &mut NegotiationState,
 ) -> io::Result<()> {
-    if nego_state.shoud_negotiate() {
+    if nego_state.shoud_negotiate(config) {
         debug!(
             "negotiating Do for supported option {:?}",
             nego_state.option
@@ -301,16 +493,81 @@ async fn do_negotiate(
 pub enum CnxOutput {
     Data(String),
     Msdp(MsdpData),
+    Sound(Sound),
+    Link(Link),
+    Atcp(AtcpMessage),
+    Gmcp(GmcpMessage),
+    Aardwolf(Aard102),
+    Latency(Duration),
+    /// the server starts (true) or stops (false) echoing the input, e.g. around a password prompt
+    ServerEcho(bool),
+    /// answer to `CnxInput::Options`
+    Options(Vec<NegotiationState>),
+    /// the connection to the address was opened
+    Connected(String),
+    /// the connection could not be opened, with the reason
+    ConnectionFailed(String),
+    /// the connection was closed, by either side
+    Disconnected,
+}
+
+/// What the client asks the connection to send
+#[derive(Debug, Clone)]
+pub enum CnxInput {
+    /// a line of text, its end of line being added
+    Line(String),
+    /// `IAC <action> <option>`
+    Negotiate(NegotiationAction, TelnetOption),
+    /// `IAC SB <option> <data> IAC SE`
+    Subnegotiate(TelnetOption, Vec<u8>),
+    /// `Package.Message <json>`
+    Gmcp(String),
+    /// an MSDP variable and its value, e.g. `SEND HEALTH`
+    Msdp(String, String),
+    /// the state of the negotiated options, answered with `CnxOutput::Options`
+    Options,
+    Disconnect,
+}
+
+/// Connects to the server then handles the connection, the client being told whether it opened
+pub async fn connect(
+    address: String,
+    config: MudConfig,
+    command_receiver: Receiver<CnxInput>,
+    mut cnx_sender: Sender<CnxOutput>,
+) -> io::Result<()> {
+    let connecting = TcpStream::connect(address.as_str());
+    let connected = match time::timeout(CONNECT_TIMEOUT, connecting).await {
+        Ok(connected) => connected,
+        Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, "connection timed out")),
+    };
+    match connected {
+        Ok(stream) => {
+            let _ = cnx_sender.send(CnxOutput::Connected(address)).await;
+            handler(Box::new(stream), config, command_receiver, cnx_sender).await
+        }
+        Err(e) => {
+            warn!("failed to connect to {} : {}", address, e);
+            let _ = cnx_sender.send(CnxOutput::ConnectionFailed(e.to_string())).await;
+            Err(e)
+        }
+    }
 }
 
 pub fn handler(
     mut tcp_stream: Box<tokio::net::TcpStream>,
-    mut command_receiver: Receiver<String>,
+    config: MudConfig,
+    mut command_receiver: Receiver<CnxInput>,
     mut cnx_sender: Sender<CnxOutput>,
 ) -> impl Future<Output = io::Result<()>> {
     async move {
-        let config = MudConfig::default();
+        let msp_enabled = config.msp;
+        let mslp_enabled = config.mslp;
+        let charset = config.charset;
         let mut cnx_state = CnxState::new();
+        cnx_state.keepalive = config
+            .keepalive
+            .map(|interval| Keepalive::new(interval, Instant::now()));
 
         let (mut telnet, mut writer): (Telnet, TelnetWriter) =
             Telnet::from_stream(tcp_stream.as_mut(), 256);
@@ -321,6 +578,7 @@ pub fn handler(
             mpsc::channel(100);
 
         let mut data_sender = cnx_sender.clone();
+        let mut closed_sender = cnx_sender.clone();
 
         let user_input = async move {
             loop {
@@ -347,10 +605,43 @@ pub fn handler(
                     Err(TryRecvError::Closed) => break,
                 };
 
+                if let Some(probe) = cnx_state
+                    .keepalive
+                    .as_mut()
+                    .and_then(|k| k.poll(Instant::now()))
+                {
+                    send_probe(&mut writer, probe).await?;
+                }
+
                 match command_receiver.try_recv() {
-                    Ok(msg) => {
-                        debug!("sending {:?}", msg);
-                        writer.write(msg.as_bytes()).await?;
+                    Ok(input) => {
+                        debug!("sending {:?}", input);
+                        match input {
+                            CnxInput::Line(msg) => {
+                                let binary = cnx_state.is_active(&TelnetOption::TransmitBinary);
+                                writer
+                                    .write(&encoder::encode_line(&msg, config.charset, binary))
+                                    .await?;
+                            }
+                            CnxInput::Negotiate(action, option) => {
+                                writer.try_negotiate(action, option).await?;
+                            }
+                            CnxInput::Subnegotiate(option, data) => {
+                                writer.try_subnegotiate(option, &[data.as_slice()]).await?;
+                            }
+                            CnxInput::Gmcp(msg) => gmcp::send(&mut writer, &msg).await?,
+                            CnxInput::Msdp(key, value) => {
+                                msdp::send_key_val(&mut writer, &key, &value).await?
+                            }
+                            CnxInput::Options => {
+                                let options = CnxOutput::Options(cnx_state.options());
+                                cnx_sender
+                                    .send(options)
+                                    .await
+                                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+                            }
+                            CnxInput::Disconnect => break,
+                        }
                         Ok(())
                     }
                     Err(TryRecvError::Empty) => {
@@ -366,13 +657,51 @@ pub fn handler(
         };
 
         let network = async move {
+            let mut msp_parser = MspParser::new();
+            let mut mslp_parser = MslpParser::new();
             loop {
-                let chunk = read_chunk(&mut telnet).await?;
+                let chunk = if msp_parser.has_pending() {
+                    time::timeout(MSP_FLUSH_DELAY, read_chunk(&mut telnet, charset))
+                        .await
+                        .ok()
+                } else {
+                    Some(read_chunk(&mut telnet, charset).await)
+                };
 
-                data_sender.send(CnxOutput::Data(chunk.data)).await;
+                let (data, sounds, negotiations) = match chunk {
+                    Some(chunk) => {
+                        let chunk = chunk?;
+                        let (data, sounds) = if msp_enabled {
+                            msp_parser.extract(&chunk.data)
+                        } else {
+                            (chunk.data, Vec::new())
+                        };
+                        (data, sounds, chunk.negotiations)
+                    }
+                    // e.g. a prompt ending with `!`
+                    None => (msp_parser.flush(), Vec::new(), Vec::new()),
+                };
+
+                let segments = if mslp_enabled {
+                    mslp_parser.extract(&data)
+                } else {
+                    vec![Segment::Text(data)]
+                };
+
+                for segment in segments.into_iter() {
+                    let output = match segment {
+                        Segment::Text(text) => CnxOutput::Data(text),
+                        Segment::Link(link) => CnxOutput::Link(link),
+                    };
+                    data_sender.send(output).await;
+                }
+
+                for sound in sounds.into_iter() {
+                    data_sender.send(CnxOutput::Sound(sound)).await;
+                }
 
-                for n in chunk.negotiations.into_iter() {
-                    nego_sender.send(n.clone());
+                for n in negotiations.into_iter() {
+                    nego_sender.send(n.clone()).await;
                 }
 
                 task::yield_now().await;
@@ -380,7 +709,15 @@ pub fn handler(
             Ok::<(), io::Error>(())
         };
 
-        tokio::join!(user_input, network);
-        Ok::<(), io::Error>(())
+        // the stream is dropped, closing the connection, as soon as either side stops
+        let result = tokio::select! {
+            result = user_input => result,
+            result = network => result,
+        };
+        if let Err(e) = &result {
+            warn!("connection closed : {}", e);
+        }
+        let _ = closed_sender.send(CnxOutput::Disconnected).await;
+        result
     }
 }
@@ -0,0 +1,102 @@
+/*
+    Mud New-Environ Standard:
+
+    https://tintin.mudhalla.net/protocols/mnes/
+
+    server - IAC SB NEW-ENVIRON SEND VAR "CLIENT_NAME" VAR "MTTS" IAC SE
+    client - IAC SB NEW-ENVIRON IS VAR "CLIENT_NAME" VAL "mudnet" VAR "MTTS" VAL "1541" IAC SE
+*/
+use std::io;
+use telnet::{TelnetOption, TelnetWriter};
+
+use super::MudConfig;
+
+const IS: u8 = 0;
+const SEND: u8 = 1;
+
+const VAR: u8 = 0;
+const VAL: u8 = 1;
+const USERVAR: u8 = 3;
+
+const VARIABLES: [&'static str; 5] = [
+    "CHARSET",
+    "CLIENT_NAME",
+    "CLIENT_VERSION",
+    "MTTS",
+    "TERMINAL_TYPE",
+];
+
+fn value(config: &MudConfig, name: &str) -> Option<String> {
+    match name {
+        "CHARSET" => Some(String::from(config.charset.name())),
+        "CLIENT_NAME" => Some(config.client_name.clone()),
+        "CLIENT_VERSION" => Some(String::from(env!("CARGO_PKG_VERSION"))),
+        "MTTS" => Some(config.features.bits().to_string()),
+        "TERMINAL_TYPE" => Some(String::from(config.terminal_type)),
+        _ => None,
+    }
+}
+
+/// Names of the variables asked by a SEND request, empty when all of them are asked
+fn requested_variables(data: &[u8]) -> io::Result<Vec<String>> {
+    match data.split_first() {
+        Some((&SEND, names)) => Ok(names
+            .split(|b| *b == VAR || *b == USERVAR)
+            .filter(|name| !name.is_empty())
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+            .collect()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("expected NEW-ENVIRON SEND, found {:?}", data),
+        )),
+    }
+}
+
+pub async fn handle_sub_negotiations(
+    telnet: &mut TelnetWriter<'_>,
+    config: &MudConfig,
+    data: &[u8],
+) -> io::Result<()> {
+    let requested = requested_variables(data)?;
+
+    let names: Vec<&str> = if requested.is_empty() {
+        VARIABLES.to_vec()
+    } else {
+        requested.iter().map(|name| name.as_str()).collect()
+    };
+
+    let mut msg: Vec<u8> = vec![IS];
+
+    for name in names {
+        if let Some(value) = value(config, name) {
+            msg.push(VAR);
+            msg.extend_from_slice(name.as_bytes());
+            msg.push(VAL);
+            msg.extend_from_slice(value.as_bytes());
+        }
+    }
+
+    telnet
+        .try_subnegotiate(TelnetOption::NewEnvironment, &[&msg])
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn send_request() -> io::Result<()> {
+        let mut data = vec![SEND, VAR];
+        data.extend_from_slice(b"CLIENT_NAME");
+        data.push(USERVAR);
+        data.extend_from_slice(b"MTTS");
+
+        assert_eq!(requested_variables(&data)?, vec!["CLIENT_NAME", "MTTS"]);
+        assert!(requested_variables(&[SEND])?.is_empty());
+        assert!(requested_variables(&[IS]).is_err());
+        Ok(())
+    }
+}
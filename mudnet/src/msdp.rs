@@ -14,8 +14,8 @@ const MSDP_ARRAY_CLOSE: u8 = 6;
 
 #[derive(Debug, Clone)]
 pub struct MsdpData {
-    key: String,
-    value: MsdpVal,
+    pub key: String,
+    pub value: MsdpVal,
 }
 
 #[derive(Debug, Clone)]
@@ -34,6 +34,11 @@ pub async fn send_key_val(telnet: &mut TelnetWriter<'_>, k: &String, v: &String)
     Ok(())
 }
 
+/// Asks the commands the server understands, answered with a COMMANDS array
+pub async fn list_commands(telnet: &mut TelnetWriter<'_>) -> io::Result<()> {
+    send_key_val(telnet, &String::from("LIST"), &String::from("COMMANDS")).await
+}
+
 enum ParsingState {
     Key,
     Value,
@@ -0,0 +1,255 @@
+/*
+    Mud Server Link Protocol:
+
+    https://tintin.mudhalla.net/protocols/mslp/
+
+    Links are delimited by OSC 68 sequences terminated by BEL or ST:
+
+    ESC ] 68 ; 1 ; <link> BEL <text> ESC ] 68 ; 2 ; <link> BEL
+
+    The link can be given by the opening or the closing sequence,
+    when both are empty the text of the link is used.
+*/
+
+const OSC_68: &'static str = "\x1b]68;";
+const BEL: char = '\x07';
+const ST: &'static str = "\x1b\\";
+
+const OPEN: &'static str = "1";
+const CLOSE: &'static str = "2";
+
+// an unterminated sequence or link longer than that is considered to be plain text
+const MAX_PENDING_LEN: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LinkTarget {
+    Command(String),
+    Url(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    pub text: String,
+    pub target: LinkTarget,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    Text(String),
+    Link(Link),
+}
+
+fn link_target(link: &str) -> LinkTarget {
+    if link.contains("://") || link.starts_with("www.") {
+        LinkTarget::Url(String::from(link))
+    } else {
+        LinkTarget::Command(String::from(link))
+    }
+}
+
+/// Finds the end of an OSC sequence, returns the position of the terminator and its length
+fn find_terminator(data: &str) -> Option<(usize, usize)> {
+    let bel = data.find(BEL).map(|i| (i, 1));
+    let st = data.find(ST).map(|i| (i, ST.len()));
+
+    match (bel, st) {
+        (Some(b), Some(s)) => Some(if b.0 < s.0 { b } else { s }),
+        (b, s) => b.or(s),
+    }
+}
+
+struct OpenLink {
+    link: String,
+    text: String,
+}
+
+/// Splits the text stream into plain text and links.
+/// Sequences and links split between two chunks are kept until the next call.
+pub struct MslpParser {
+    pending: String,
+    open: Option<OpenLink>,
+}
+
+impl MslpParser {
+    pub fn new() -> MslpParser {
+        MslpParser {
+            pending: String::new(),
+            open: None,
+        }
+    }
+
+    fn push_text(&mut self, segments: &mut Vec<Segment>, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        match self.open.as_mut() {
+            Some(open) => open.text.push_str(text),
+            None => match segments.last_mut() {
+                Some(Segment::Text(t)) => t.push_str(text),
+                _ => segments.push(Segment::Text(String::from(text))),
+            },
+        }
+    }
+
+    /// an unterminated link is given back as plain text
+    fn abort_link(&mut self, segments: &mut Vec<Segment>) {
+        if let Some(open) = self.open.take() {
+            self.push_text(segments, &open.text);
+        }
+    }
+
+    fn handle_sequence(&mut self, segments: &mut Vec<Segment>, body: &str) {
+        let mut params = body.splitn(2, ';');
+        let code = params.next().unwrap_or("");
+        let link = params.next().unwrap_or("");
+
+        match code {
+            OPEN => {
+                self.abort_link(segments);
+                self.open = Some(OpenLink {
+                    link: String::from(link),
+                    text: String::new(),
+                });
+            }
+            CLOSE => {
+                if let Some(open) = self.open.take() {
+                    let target = [open.link.as_str(), link, open.text.as_str()]
+                        .iter()
+                        .map(|l| l.trim())
+                        .find(|l| !l.is_empty())
+                        .map(link_target);
+
+                    match target {
+                        Some(target) => segments.push(Segment::Link(Link {
+                            text: open.text,
+                            target,
+                        })),
+                        None => self.push_text(segments, &open.text),
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    pub fn extract(&mut self, data: &str) -> Vec<Segment> {
+        let mut input = std::mem::replace(&mut self.pending, String::new());
+        input.push_str(data);
+
+        let mut segments: Vec<Segment> = Vec::new();
+        let mut rest = input.as_str();
+
+        loop {
+            let start = match rest.find(OSC_68) {
+                Some(start) => start,
+                None => {
+                    // keep what may be the beginning of a sequence
+                    let keep = rest
+                        .rfind('\x1b')
+                        .filter(|i| OSC_68.starts_with(&rest[*i..]))
+                        .unwrap_or(rest.len());
+                    self.push_text(&mut segments, &rest[..keep]);
+                    self.pending.push_str(&rest[keep..]);
+                    break;
+                }
+            };
+
+            self.push_text(&mut segments, &rest[..start]);
+            let sequence = &rest[start + OSC_68.len()..];
+
+            match find_terminator(sequence) {
+                Some((end, len)) => {
+                    self.handle_sequence(&mut segments, &sequence[..end]);
+                    rest = &sequence[end + len..];
+                }
+                None if sequence.len() < MAX_PENDING_LEN => {
+                    self.pending.push_str(&rest[start..]);
+                    break;
+                }
+                None => {
+                    self.push_text(&mut segments, &rest[start..]);
+                    break;
+                }
+            }
+        }
+
+        if self
+            .open
+            .as_ref()
+            .map_or(false, |open| open.text.len() > MAX_PENDING_LEN)
+        {
+            self.abort_link(&mut segments);
+        }
+
+        segments
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> Segment {
+        Segment::Text(String::from(s))
+    }
+
+    #[test]
+    fn command_link() {
+        let mut parser = MslpParser::new();
+        let segments = parser.extract("Exits: \x1b]68;1;north\x07North\x1b]68;2\x07 and south");
+
+        assert_eq!(
+            segments,
+            vec![
+                text("Exits: "),
+                Segment::Link(Link {
+                    text: String::from("North"),
+                    target: LinkTarget::Command(String::from("north")),
+                }),
+                text(" and south"),
+            ]
+        );
+    }
+
+    #[test]
+    fn url_link_on_close_with_st() {
+        let mut parser = MslpParser::new();
+        let segments = parser.extract("\x1b]68;1;\x1b\\site\x1b]68;2;https://mud.org\x1b\\");
+
+        assert_eq!(
+            segments,
+            vec![Segment::Link(Link {
+                text: String::from("site"),
+                target: LinkTarget::Url(String::from("https://mud.org")),
+            })]
+        );
+    }
+
+    #[test]
+    fn link_split_between_chunks() {
+        let mut parser = MslpParser::new();
+
+        assert_eq!(parser.extract("look at the \x1b]6"), vec![text("look at the ")]);
+        assert_eq!(parser.extract("8;1\x07sword"), vec![]);
+        assert_eq!(
+            parser.extract("\x1b]68;2\x07."),
+            vec![
+                Segment::Link(Link {
+                    text: String::from("sword"),
+                    target: LinkTarget::Command(String::from("sword")),
+                }),
+                text("."),
+            ]
+        );
+    }
+
+    #[test]
+    fn other_escape_sequences_are_kept() {
+        let mut parser = MslpParser::new();
+        assert_eq!(
+            parser.extract("\x1b[36mcyan\x1b[0m\x1b"),
+            vec![text("\x1b[36mcyan\x1b[0m")]
+        );
+        assert_eq!(parser.extract("[1m"), vec![text("\x1b[1m")]);
+    }
+}
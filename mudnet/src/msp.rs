@@ -0,0 +1,293 @@
+/*
+    Mud Sound Protocol:
+
+    https://www.zuggsoft.com/zmud/msp.htm
+
+    !!SOUND(fname V=volume L=repeats P=priority T=type U=url)
+    !!MUSIC(fname V=volume L=repeats C=continue T=type U=url)
+*/
+use log::warn;
+
+const SOUND_TRIGGER: &'static str = "!!SOUND(";
+const MUSIC_TRIGGER: &'static str = "!!MUSIC(";
+const TRIGGER_LEN: usize = 8;
+
+// a trigger still unterminated after that many bytes is considered to be plain text
+const MAX_TRIGGER_LEN: usize = 512;
+
+pub const OFF: &'static str = "Off";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SoundKind {
+    Sound,
+    Music,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sound {
+    pub kind: SoundKind,
+    pub file: String,
+    /// 0 to 100
+    pub volume: u8,
+    /// number of times the file is played, -1 means until told otherwise
+    pub loops: i32,
+    /// 0 to 100, only meaningful for sounds
+    pub priority: u8,
+    /// only meaningful for music: keep playing if the same file is requested again
+    pub continues: bool,
+    pub sound_type: Option<String>,
+    pub url: Option<String>,
+}
+
+impl Sound {
+    fn new(kind: SoundKind, file: String) -> Sound {
+        Sound {
+            kind,
+            file,
+            volume: 100,
+            loops: 1,
+            priority: 50,
+            continues: true,
+            sound_type: None,
+            url: None,
+        }
+    }
+
+    /// `!!SOUND(Off)` and `!!MUSIC(Off)` stop whatever is currently played
+    pub fn is_off(&self) -> bool {
+        self.file == OFF && self.url.is_none()
+    }
+}
+
+enum TriggerMatch {
+    Trigger(SoundKind),
+    Prefix,
+    NoMatch,
+}
+
+fn match_trigger(candidate: &str) -> TriggerMatch {
+    if candidate.starts_with(SOUND_TRIGGER) {
+        TriggerMatch::Trigger(SoundKind::Sound)
+    } else if candidate.starts_with(MUSIC_TRIGGER) {
+        TriggerMatch::Trigger(SoundKind::Music)
+    } else if candidate.len() < TRIGGER_LEN
+        && (SOUND_TRIGGER.starts_with(candidate) || MUSIC_TRIGGER.starts_with(candidate))
+    {
+        TriggerMatch::Prefix
+    } else {
+        TriggerMatch::NoMatch
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(key: &str, value: &str) -> Option<T> {
+    match value.parse() {
+        Ok(v) => Some(v),
+        Err(_) => {
+            warn!("invalid MSP value for {} : {:?}", key, value);
+            None
+        }
+    }
+}
+
+fn parse_trigger(kind: SoundKind, args: &str) -> Option<Sound> {
+    let mut words = args.split_whitespace();
+
+    let mut sound = Sound::new(kind, String::from(words.next()?));
+
+    for word in words {
+        let mut kv = word.splitn(2, '=');
+        let key = kv.next().unwrap_or("");
+        let value = kv.next().unwrap_or("");
+
+        match key {
+            "V" => {
+                if let Some(v) = parse_number::<u8>(key, value) {
+                    sound.volume = v.min(100)
+                }
+            }
+            "L" => {
+                if let Some(v) = parse_number(key, value) {
+                    sound.loops = v
+                }
+            }
+            "P" => {
+                if let Some(v) = parse_number::<u8>(key, value) {
+                    sound.priority = v.min(100)
+                }
+            }
+            "C" => {
+                if let Some(v) = parse_number::<u8>(key, value) {
+                    sound.continues = v != 0
+                }
+            }
+            "T" => sound.sound_type = Some(String::from(value)),
+            "U" => sound.url = Some(String::from(value)),
+            _ => warn!("ignoring unknown MSP parameter {:?}", word),
+        }
+    }
+
+    Some(sound)
+}
+
+/// Extracts MSP triggers from the text stream.
+/// A trigger split between two chunks is kept until the next call, a trailing `!` included,
+/// unless it is flushed first when no chunk follows it.
+pub struct MspParser {
+    pending: String,
+}
+
+impl MspParser {
+    pub fn new() -> MspParser {
+        MspParser {
+            pending: String::new(),
+        }
+    }
+
+    /// returns the data stripped from its triggers and the triggers found
+    pub fn extract(&mut self, data: &str) -> (String, Vec<Sound>) {
+        let mut input = std::mem::replace(&mut self.pending, String::new());
+        input.push_str(data);
+
+        let mut text = String::new();
+        let mut sounds: Vec<Sound> = Vec::new();
+        let mut rest = input.as_str();
+
+        loop {
+            let start = match rest.find("!!") {
+                Some(start) => start,
+                None => {
+                    if rest.ends_with('!') {
+                        text.push_str(&rest[..rest.len() - 1]);
+                        self.pending.push('!');
+                    } else {
+                        text.push_str(rest);
+                    }
+                    break;
+                }
+            };
+
+            text.push_str(&rest[..start]);
+            let candidate = &rest[start..];
+
+            match match_trigger(candidate) {
+                TriggerMatch::Prefix => {
+                    self.pending.push_str(candidate);
+                    break;
+                }
+                TriggerMatch::NoMatch => {
+                    text.push('!');
+                    rest = &candidate[1..];
+                }
+                TriggerMatch::Trigger(kind) => match candidate.find(')') {
+                    Some(end) => {
+                        match parse_trigger(kind, &candidate[TRIGGER_LEN..end]) {
+                            Some(sound) => sounds.push(sound),
+                            None => text.push_str(&candidate[..end + 1]),
+                        }
+                        rest = &candidate[end + 1..];
+                    }
+                    None if candidate.len() < MAX_TRIGGER_LEN => {
+                        self.pending.push_str(candidate);
+                        break;
+                    }
+                    None => {
+                        text.push_str(candidate);
+                        break;
+                    }
+                },
+            }
+        }
+
+        (text, sounds)
+    }
+
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// The text kept for a trigger which did not follow, e.g. a prompt ending with `!`
+    pub fn flush(&mut self) -> String {
+        std::mem::replace(&mut self.pending, String::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sound_with_parameters() {
+        let mut parser = MspParser::new();
+        let (text, sounds) =
+            parser.extract("You hit.!!SOUND(hit.wav V=80 L=2 P=70 T=combat U=http://mud/s/) Ouch");
+
+        assert_eq!(text, "You hit. Ouch");
+        assert_eq!(sounds.len(), 1);
+        let sound = &sounds[0];
+        assert_eq!(sound.kind, SoundKind::Sound);
+        assert_eq!(sound.file, "hit.wav");
+        assert_eq!(sound.volume, 80);
+        assert_eq!(sound.loops, 2);
+        assert_eq!(sound.priority, 70);
+        assert_eq!(sound.sound_type, Some(String::from("combat")));
+        assert_eq!(sound.url, Some(String::from("http://mud/s/")));
+    }
+
+    #[test]
+    fn music_defaults_and_off() {
+        let mut parser = MspParser::new();
+        let (text, sounds) = parser.extract("!!MUSIC(town.mid L=-1 C=0)\r\n!!MUSIC(Off)");
+
+        assert_eq!(text, "\r\n");
+        assert_eq!(sounds.len(), 2);
+        assert_eq!(sounds[0].kind, SoundKind::Music);
+        assert_eq!(sounds[0].volume, 100);
+        assert_eq!(sounds[0].loops, -1);
+        assert!(!sounds[0].continues);
+        assert!(sounds[1].is_off());
+    }
+
+    #[test]
+    fn trigger_split_between_chunks() {
+        let mut parser = MspParser::new();
+
+        let (text, sounds) = parser.extract("A door opens !");
+        assert_eq!(text, "A door opens ");
+        assert!(sounds.is_empty());
+
+        let (text, sounds) = parser.extract("!SOUND(door");
+        assert_eq!(text, "");
+        assert!(sounds.is_empty());
+
+        let (text, sounds) = parser.extract(".wav) slowly.");
+        assert_eq!(text, " slowly.");
+        assert_eq!(sounds[0].file, "door.wav");
+    }
+
+    #[test]
+    fn plain_exclamations_are_kept() {
+        let mut parser = MspParser::new();
+        let (text, sounds) = parser.extract("Wow!!! !!SOUNDS great!!");
+        assert_eq!(text, "Wow!!! !!SOUNDS great");
+        assert!(sounds.is_empty());
+        let (text, _) = parser.extract("\r\n");
+        assert_eq!(text, "!!\r\n");
+        let (text, _) = parser.extract("Attack! ");
+        assert_eq!(text, "Attack! ");
+    }
+
+    #[test]
+    fn flushes_a_trailing_exclamation() {
+        let mut parser = MspParser::new();
+        let (text, _) = parser.extract("Ready!");
+        assert_eq!(text, "Ready");
+        assert!(parser.has_pending());
+        assert_eq!(parser.flush(), "!");
+        assert!(!parser.has_pending());
+
+        parser.extract("A door opens !!");
+        let (text, sounds) = parser.extract("SOUND(door.wav)\r\n");
+        assert_eq!(text, "\r\n");
+        assert_eq!(sounds[0].file, "door.wav");
+    }
+}
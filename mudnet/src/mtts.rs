@@ -20,6 +20,7 @@ bitflags! {
         const PROXY             = 0b0000_1000_0000;
         const TRUECOLOR         = 0b0001_0000_0000;
         const MNES              = 0b0010_0000_0000;
+        const MSLP              = 0b0100_0000_0000;
     }
 }
 
@@ -0,0 +1,430 @@
+use std::collections::HashMap;
+
+use log::warn;
+use regex::{Captures, Regex};
+
+use crate::command;
+use crate::config::CommandConfig;
+use crate::speedwalk::Speedwalk;
+use crate::world::AliasConfig;
+
+/// Deepest nesting of aliases calling other aliases
+const MAX_DEPTH: usize = 10;
+
+enum Matcher {
+    /// first word of the command
+    Word(String),
+    Regex(Regex),
+}
+
+/// A command replaced by other ones
+pub struct Alias {
+    /// a word, or a regex between slashes, as typed
+    pattern: String,
+    matcher: Matcher,
+    /// commands separated by the command separator, with `%1`, `%*` or `%{name}` placeholders
+    body: String,
+    /// defined by a script, not saved
+    scripted: bool,
+}
+
+/// Arguments of a matched command, substituted in the body of the alias
+enum Args<'a> {
+    Words(&'a str),
+    Captures(Captures<'a>),
+    /// groups collected over several lines, with the lines matched
+    Groups(&'a [String], &'a HashMap<String, String>, &'a str),
+}
+
+/// Splits `/regex/ body` or `word body` into the pattern and the body
+pub fn split_definition(definition: &str) -> (&str, &str) {
+    let definition = definition.trim();
+    let end = if definition.starts_with('/') {
+        definition[1..]
+            .find("/ ")
+            .map(|i| i + 2)
+            .unwrap_or(definition.len())
+    } else {
+        definition.find(char::is_whitespace).unwrap_or(definition.len())
+    };
+    (&definition[..end], definition[end..].trim())
+}
+
+impl Alias {
+    pub fn new(pattern: &str, body: &str) -> Result<Alias, regex::Error> {
+        let is_regex = pattern.len() > 1 && pattern.starts_with('/') && pattern.ends_with('/');
+        let matcher = if is_regex {
+            Matcher::Regex(Regex::new(&pattern[1..pattern.len() - 1])?)
+        } else {
+            Matcher::Word(String::from(pattern))
+        };
+        Ok(Alias {
+            pattern: String::from(pattern),
+            matcher,
+            body: String::from(body),
+            scripted: false,
+        })
+    }
+
+    /// The alias defined by a script, dropped when the scripts are run again
+    pub fn scripted(mut self) -> Alias {
+        self.scripted = true;
+        self
+    }
+
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn body(&self) -> &str {
+        &self.body
+    }
+
+    /// The word the alias is called with, for the completion
+    pub fn word(&self) -> Option<&str> {
+        match &self.matcher {
+            Matcher::Word(word) => Some(word),
+            Matcher::Regex(_) => None,
+        }
+    }
+
+    fn args<'a>(&self, command: &'a str) -> Option<Args<'a>> {
+        match &self.matcher {
+            Matcher::Word(word) => {
+                let command = command.trim_start();
+                let end = command.find(char::is_whitespace).unwrap_or(command.len());
+                if &command[..end] == word {
+                    Some(Args::Words(command[end..].trim()))
+                } else {
+                    None
+                }
+            }
+            Matcher::Regex(regex) => regex.captures(command).map(Args::Captures),
+        }
+    }
+
+    /// The body with the placeholders replaced, `None` if the command does not match.
+    /// The arguments of a word alias are appended when its body has no placeholder.
+    fn apply(&self, command: &str, config: &CommandConfig) -> Option<String> {
+        let args = self.args(command)?;
+        let (expanded, substituted) = substitute(&self.body, &args, Some(config));
+        match args {
+            Args::Words(words) if !substituted && !words.is_empty() => {
+                let at_start = is_command_start(&expanded, config);
+                Some(format!("{} {}", expanded, escape(words, at_start, config)))
+            }
+            _ => Some(expanded),
+        }
+    }
+}
+
+/// An argument substituted in a command, kept a part of it: its separators are escaped,
+/// and a prefix starting the command is doubled so that the argument is sent as text
+pub fn escape(arg: &str, at_start: bool, config: &CommandConfig) -> String {
+    let separator = &config.separator;
+    let mut escaped = if separator.is_empty() {
+        String::from(arg)
+    } else {
+        arg.replace('\\', "\\\\")
+            .replace(separator.as_str(), &format!("\\{}", separator))
+    };
+    let prefix = &config.prefix;
+    let start = escaped.len() - escaped.trim_start().len();
+    if at_start && !prefix.is_empty() && escaped[start..].starts_with(prefix.as_str()) {
+        escaped.insert_str(start, prefix);
+    }
+    escaped
+}
+
+/// Whether a text appended would start a command, the text being blank or ending with a separator
+fn is_command_start(text: &str, config: &CommandConfig) -> bool {
+    let last = command::split(text, &config.separator).pop();
+    last.map_or(true, |last| last.trim().is_empty())
+}
+
+/// Replaces `%1` to `%9`, `%*` and `%{name}` in the body, `%%` giving a `%`.
+/// `%*` is the text following the word of a word alias, and the whole match of a regex alias.
+/// The arguments are escaped when the body is a command, see `escape`.
+fn substitute(body: &str, args: &Args, config: Option<&CommandConfig>) -> (String, bool) {
+    let mut expanded = String::new();
+    let mut substituted = false;
+    let mut rest = body;
+
+    while let Some(i) = rest.find('%') {
+        expanded.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+
+        let next = rest.chars().next();
+        let (value, len) = match next {
+            Some('%') => (Some(String::from("%")), 1),
+            Some('*') => (Some(arg(args, None)), 1),
+            Some(c) if c.is_ascii_digit() => (Some(arg(args, c.to_digit(10))), 1),
+            Some('{') => match rest.find('}') {
+                Some(end) => (Some(named_arg(args, &rest[1..end])), end + 1),
+                None => (None, 0),
+            },
+            _ => (None, 0),
+        };
+        match value {
+            Some(value) => {
+                let is_arg = next != Some('%');
+                substituted = substituted || is_arg;
+                match config {
+                    Some(config) if is_arg => {
+                        let at_start = is_command_start(&expanded, config);
+                        expanded.push_str(&escape(&value, at_start, config));
+                    }
+                    _ => expanded.push_str(&value),
+                }
+            }
+            None => expanded.push('%'),
+        }
+        rest = &rest[len..];
+    }
+    expanded.push_str(rest);
+    (expanded, substituted)
+}
+
+/// The body with the groups of a regex match substituted, as in a regex alias
+pub fn substitute_captures(body: &str, captures: Captures) -> String {
+    substitute(body, &Args::Captures(captures), None).0
+}
+
+/// The action of a trigger with the groups of a match substituted,
+/// the server text they hold being escaped so that it cannot run other commands
+pub fn substitute_action(action: &str, captures: Captures, config: &CommandConfig) -> String {
+    substitute(action, &Args::Captures(captures), Some(config)).0
+}
+
+/// The action with groups collected over several matches substituted, `%*` being the text matched,
+/// escaped as in `substitute_action`
+pub fn substitute_groups(
+    action: &str,
+    groups: &[String],
+    names: &HashMap<String, String>,
+    matched: &str,
+    config: &CommandConfig,
+) -> String {
+    substitute(action, &Args::Groups(groups, names, matched), Some(config)).0
+}
+
+/// The argument at the given position, or all of them
+fn arg(args: &Args, position: Option<u32>) -> String {
+    let text = match (args, position) {
+        (Args::Words(words), None) => Some(*words),
+        (Args::Words(words), Some(n)) => (n as usize)
+            .checked_sub(1)
+            .and_then(|n| words.split_whitespace().nth(n)),
+        (Args::Captures(captures), None) => captures.get(0).map(|m| m.as_str()),
+        (Args::Captures(captures), Some(n)) => captures.get(n as usize).map(|m| m.as_str()),
+        (Args::Groups(_, _, matched), None) => Some(*matched),
+        (Args::Groups(groups, _, _), Some(n)) => (n as usize)
+            .checked_sub(1)
+            .and_then(|n| groups.get(n))
+            .map(String::as_str),
+    };
+    String::from(text.unwrap_or(""))
+}
+
+fn named_arg(args: &Args, name: &str) -> String {
+    let text = match args {
+        Args::Captures(captures) => captures.name(name).map_or("", |m| m.as_str()),
+        Args::Groups(_, names, _) => names.get(name).map_or("", String::as_str),
+        Args::Words(_) => "",
+    };
+    String::from(text)
+}
+
+/// The aliases of the world, tried in the order they were defined
+#[derive(Default)]
+pub struct Aliases {
+    aliases: Vec<Alias>,
+}
+
+impl Aliases {
+    /// Compiles the saved aliases, the invalid ones being skipped
+    pub fn from_config(configs: &[AliasConfig]) -> Aliases {
+        let mut aliases = Aliases::default();
+        for config in configs {
+            match Alias::new(&config.pattern, &config.body) {
+                Ok(alias) => aliases.set(alias),
+                Err(e) => warn!("invalid alias {} : {}", config.pattern, e),
+            }
+        }
+        aliases
+    }
+
+    /// The aliases to save, the ones of the scripts being left out
+    pub fn to_config(&self) -> Vec<AliasConfig> {
+        self.aliases
+            .iter()
+            .filter(|alias| !alias.scripted)
+            .map(|alias| AliasConfig {
+                pattern: alias.pattern.clone(),
+                body: alias.body.clone(),
+            })
+            .collect()
+    }
+
+    /// Adds an alias, replacing the one with the same pattern
+    pub fn set(&mut self, alias: Alias) {
+        match self.aliases.iter_mut().find(|a| a.pattern == alias.pattern) {
+            Some(existing) => *existing = alias,
+            None => self.aliases.push(alias),
+        }
+    }
+
+    pub fn remove(&mut self, pattern: &str) -> bool {
+        let len = self.aliases.len();
+        self.aliases.retain(|a| a.pattern != pattern);
+        self.aliases.len() != len
+    }
+
+    pub fn remove_scripted(&mut self) {
+        self.aliases.retain(|a| !a.scripted);
+    }
+
+    /// Whether the user defined an alias with the pattern, which the scripts do not replace
+    pub fn is_user_defined(&self, pattern: &str) -> bool {
+        self.aliases.iter().any(|a| a.pattern == pattern && !a.scripted)
+    }
+
+    pub fn get(&self, pattern: &str) -> Option<&Alias> {
+        self.aliases.iter().find(|a| a.pattern == pattern)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Alias> {
+        self.aliases.iter()
+    }
+
+    /// Commands a command expands to, itself if no alias matches.
+    /// An alias is not expanded again in its own expansion.
+    /// The walks of the bodies are kept whole, to be expanded by the speedwalk.
+    pub fn expand(
+        &self,
+        command: &str,
+        config: &CommandConfig,
+        speedwalk: &Speedwalk,
+    ) -> Vec<String> {
+        let mut commands = Vec::new();
+        self.expand_into(command, config, speedwalk, &mut Vec::new(), &mut commands);
+        commands
+    }
+
+    fn expand_into(
+        &self,
+        command: &str,
+        config: &CommandConfig,
+        speedwalk: &Speedwalk,
+        active: &mut Vec<usize>,
+        commands: &mut Vec<String>,
+    ) {
+        let found = self
+            .aliases
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !active.contains(i))
+            .find_map(|(i, alias)| alias.apply(command, config).map(|body| (i, body)));
+
+        match found {
+            Some(_) if active.len() == MAX_DEPTH => {
+                warn!("aliases nested too deeply, {} is sent as is", command);
+                commands.push(String::from(command));
+            }
+            Some((i, body)) => {
+                active.push(i);
+                for command in command::split(&body, &config.separator) {
+                    // `3n2e` is a walk, not `n2e` 3 times
+                    if speedwalk.expand(&command).is_some() {
+                        commands.push(command);
+                        continue;
+                    }
+                    for command in command::repeated(&command, config.max_repeat) {
+                        self.expand_into(&command, config, speedwalk, active, commands);
+                    }
+                }
+                active.pop();
+            }
+            None => commands.push(String::from(command)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SpeedwalkConfig;
+
+    fn aliases(definitions: &[(&str, &str)]) -> Aliases {
+        let mut aliases = Aliases::default();
+        for (pattern, body) in definitions {
+            aliases.set(Alias::new(pattern, body).unwrap());
+        }
+        aliases
+    }
+
+    fn expand(aliases: &Aliases, command: &str) -> Vec<String> {
+        let config = CommandConfig::default();
+        let speedwalk = Speedwalk::new(&SpeedwalkConfig::default(), config.max_repeat);
+        aliases.expand(command, &config, &speedwalk)
+    }
+
+    #[test]
+    fn substitutes_arguments() {
+        let aliases = aliases(&[
+            ("k", "kill"),
+            ("gt", "tell %1 %*"),
+            ("/^tt (?P<who>\\w+) (.*)$/", "tell %{who} %2 100%%"),
+        ]);
+
+        assert_eq!(expand(&aliases, "k rat"), vec!["kill rat"]);
+        assert_eq!(expand(&aliases, "kick rat"), vec!["kick rat"]);
+        assert_eq!(expand(&aliases, "gt bob hi there"), vec!["tell bob bob hi there"]);
+        assert_eq!(expand(&aliases, "tt bob hi"), vec!["tell bob hi 100%"]);
+    }
+
+    #[test]
+    fn expands_nested_aliases() {
+        let aliases = aliases(&[
+            ("gc", "get all corpse;sac"),
+            ("sac", "sacrifice corpse"),
+            ("loop", "say loop;loop"),
+            ("n", "#2 north"),
+            ("home", "3n2e;enter"),
+        ]);
+
+        assert_eq!(expand(&aliases, "gc"), vec!["get all corpse", "sacrifice corpse"]);
+        assert_eq!(expand(&aliases, "loop"), vec!["say loop", "loop"]);
+        assert_eq!(expand(&aliases, "n"), vec!["north", "north"]);
+        assert_eq!(expand(&aliases, "home"), vec!["3n2e", "enter"]);
+    }
+
+    #[test]
+    fn escapes_the_arguments() {
+        let aliases = aliases(&[("greet", "say hi %1;bow"), ("do", "%*"), ("yell", "shout")]);
+
+        assert_eq!(expand(&aliases, "greet bob;:set x"), vec!["say hi bob;:set", "bow"]);
+        assert_eq!(expand(&aliases, "do :set x 1"), vec!["::set x 1"]);
+        assert_eq!(expand(&aliases, "yell a;b"), vec!["shout a;b"]);
+    }
+
+    #[test]
+    fn keeps_the_aliases_of_the_user() {
+        let mut aliases = aliases(&[("h", "heal")]);
+        aliases.set(Alias::new("b", "bash").unwrap().scripted());
+
+        assert!(aliases.is_user_defined("h"));
+        assert!(!aliases.is_user_defined("b"));
+        assert_eq!(aliases.to_config().len(), 1);
+        aliases.remove_scripted();
+        assert_eq!(aliases.iter().count(), 1);
+        assert_eq!(aliases.get("h").map(Alias::body), Some("heal"));
+    }
+
+    #[test]
+    fn splits_definitions() {
+        assert_eq!(split_definition("k kill %1"), ("k", "kill %1"));
+        assert_eq!(split_definition("/^a b$/ say %*"), ("/^a b$/", "say %*"));
+        assert_eq!(split_definition(" gc"), ("gc", ""));
+    }
+}
@@ -0,0 +1,379 @@
+use log::debug;
+use mudnet::mslp::Link;
+use termit_ansi::model::AnsiHandler;
+use termit_ansi::{
+    model::{Ansi as TAnsi, AnsiError, Ctl},
+    parser::AnsiParser,
+};
+use tui::style::{Color, Modifier, Style};
+
+use crate::color::{ansi_color, downsample_style, ColorDepth};
+
+const SGR: u32 = 'm' as u32;
+const TAB: &'static str = "    ";
+
+#[derive(Debug)]
+pub enum Ansi {
+    /// The error and raw bytes that are invalid
+    Error(AnsiError, Vec<u8>),
+    /// Escape - either as part of a sequence or on it's own
+    Esc,
+    /// normal or unicode character
+    /// * <c>+
+    Data(String),
+    /// Ansi command
+    Command(Ctl, u32, String, Vec<u8>),
+}
+
+#[derive(Debug)]
+pub struct AnsiVec {
+    buf: Vec<Ansi>,
+}
+
+impl AnsiVec {
+    pub fn new() -> AnsiVec {
+        AnsiVec { buf: Vec::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Merges the consecutive `Ansi::Data`
+    pub fn compact(&mut self) {
+        let init: Option<String> = None;
+        let mut new_buf = Vec::new();
+
+        let end = self
+            .buf
+            .drain(..)
+            .into_iter()
+            .fold(init, |current, a| -> Option<String> {
+                match a {
+                    Ansi::Data(s) => {
+                        let mut cs = current.unwrap_or(String::new());
+                        cs.push_str(s.as_str());
+                        Some(cs)
+                    }
+                    other => {
+                        current.map(|s| -> () { new_buf.push(Ansi::Data(s)) });
+                        new_buf.push(other);
+                        None
+                    }
+                }
+            });
+
+        end.map(|s| -> () { new_buf.push(Ansi::Data(s)) });
+
+        self.buf = new_buf;
+    }
+
+    pub fn clear(&mut self) {
+        self.buf.clear()
+    }
+}
+
+impl AnsiHandler for AnsiVec {
+    fn handle(&mut self, tansi: TAnsi, _raw: &[u8]) {
+        let ansi = match tansi {
+            TAnsi::Data(str) => Ansi::Data(String::from(str)),
+            TAnsi::Esc => Ansi::Esc,
+            TAnsi::Command(c, f, p, t) => Ansi::Command(c, f, String::from(p), Vec::from(t)),
+            TAnsi::Error(err, raw) => Ansi::Error(err, Vec::from(raw)),
+        };
+        self.buf.push(ansi)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub style: Style,
+    /// set when the span is (part of) a MSLP link
+    pub link: Option<Link>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StyledLine {
+    pub spans: Vec<Span>,
+}
+
+impl StyledLine {
+    pub fn new() -> StyledLine {
+        StyledLine { spans: Vec::new() }
+    }
+
+    pub fn styled(text: &str, style: Style) -> StyledLine {
+        let mut line = StyledLine::new();
+        line.push(text, style, None);
+        line
+    }
+
+    /// Appends some text, merged with the last span when it has the same style
+    pub fn push(&mut self, text: &str, style: Style, link: Option<&Link>) {
+        if text.is_empty() {
+            return;
+        }
+        match self.spans.last_mut() {
+            Some(last) if last.style == style && last.link.as_ref() == link => {
+                last.text.push_str(text)
+            }
+            _ => self.spans.push(Span {
+                text: String::from(text),
+                style,
+                link: link.cloned(),
+            }),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    /// The text without its style
+    pub fn text(&self) -> String {
+        self.spans.iter().map(|span| span.text.as_str()).collect()
+    }
+
+    /// Replaces the text between two byte offsets of `text()`,
+    /// the new text taking the style of the first character replaced
+    pub fn replace_range(&mut self, start: usize, end: usize, text: &str) {
+        let total: usize = self.spans.iter().map(|span| span.text.len()).sum();
+        let mut line = StyledLine::new();
+        let mut inserted = false;
+        let mut offset = 0;
+
+        for span in self.spans.drain(..) {
+            let (s, e) = (offset, offset + span.text.len());
+            offset = e;
+            if s < start {
+                line.push(&span.text[..start.min(e) - s], span.style, span.link.as_ref());
+            }
+            if !inserted && s <= start && (start < e || e == total) {
+                line.push(text, span.style, span.link.as_ref());
+                inserted = true;
+            }
+            if e > end {
+                line.push(&span.text[end.max(s) - s..], span.style, span.link.as_ref());
+            }
+        }
+        if !inserted {
+            line.push(text, Style::default(), None);
+        }
+        self.spans = line.spans;
+    }
+}
+
+/// Reads the color of a `38;5;n` or `38;2;r;g;b` sequence, the codes starting after 38 (or 48).
+/// Returns the color and the number of codes it is made of.
+fn extended_color(codes: &[u32]) -> (Option<Color>, usize) {
+    let byte = |i: usize| codes.get(i).map(|c| (*c).min(255) as u8);
+
+    match codes.first() {
+        Some(5) => (byte(1).map(Color::Indexed), 2),
+        Some(2) => match (byte(1), byte(2), byte(3)) {
+            (Some(r), Some(g), Some(b)) => (Some(Color::Rgb(r, g, b)), 4),
+            _ => (None, codes.len()),
+        },
+        _ => (None, codes.len()),
+    }
+}
+
+/// Applies the parameters of a SGR sequence (`ESC [ <params> m`) to the current style
+pub fn apply_sgr(style: Style, params: &str) -> Style {
+    let codes: Vec<u32> = if params.is_empty() {
+        vec![0]
+    } else {
+        params
+            .split(';')
+            .map(|p| p.parse().unwrap_or(0))
+            .collect()
+    };
+
+    let mut style = style;
+    let mut i = 0;
+
+    while i < codes.len() {
+        let code = codes[i];
+        i += 1;
+
+        match code {
+            0 => style = Style::default(),
+            1 => style.modifier.insert(Modifier::BOLD),
+            2 => style.modifier.insert(Modifier::DIM),
+            3 => style.modifier.insert(Modifier::ITALIC),
+            4 => style.modifier.insert(Modifier::UNDERLINED),
+            5 => style.modifier.insert(Modifier::SLOW_BLINK),
+            6 => style.modifier.insert(Modifier::RAPID_BLINK),
+            7 => style.modifier.insert(Modifier::REVERSED),
+            8 => style.modifier.insert(Modifier::HIDDEN),
+            9 => style.modifier.insert(Modifier::CROSSED_OUT),
+            21 | 22 => style.modifier.remove(Modifier::BOLD | Modifier::DIM),
+            23 => style.modifier.remove(Modifier::ITALIC),
+            24 => style.modifier.remove(Modifier::UNDERLINED),
+            25 => style
+                .modifier
+                .remove(Modifier::SLOW_BLINK | Modifier::RAPID_BLINK),
+            27 => style.modifier.remove(Modifier::REVERSED),
+            28 => style.modifier.remove(Modifier::HIDDEN),
+            29 => style.modifier.remove(Modifier::CROSSED_OUT),
+            30..=37 => style.fg = ansi_color((code - 30) as u8),
+            38 | 48 => {
+                let (color, len) = extended_color(&codes[i..]);
+                i += len;
+                match (code, color) {
+                    (38, Some(color)) => style.fg = color,
+                    (48, Some(color)) => style.bg = color,
+                    _ => debug!("invalid extended color {:?}", codes),
+                }
+            }
+            39 => style.fg = Color::Reset,
+            40..=47 => style.bg = ansi_color((code - 40) as u8),
+            49 => style.bg = Color::Reset,
+            90..=97 => style.fg = ansi_color((code - 90 + 8) as u8),
+            100..=107 => style.bg = ansi_color((code - 100 + 8) as u8),
+            _ => debug!("ignoring SGR code {}", code),
+        }
+    }
+
+    style
+}
+
+/// Turns the server data into styled lines.
+/// The style and the line being received are kept between two chunks.
+pub struct StyledParser {
+    parser: AnsiParser<[u8; 32]>,
+    handler: AnsiVec,
+    /// colors the server sends beyond that depth are downsampled
+    depth: ColorDepth,
+    style: Style,
+    line: StyledLine,
+}
+
+impl StyledParser {
+    pub fn new(depth: ColorDepth) -> StyledParser {
+        StyledParser {
+            parser: AnsiParser::new([0u8; 32]),
+            handler: AnsiVec::new(),
+            depth,
+            style: Style::default(),
+            line: StyledLine::new(),
+        }
+    }
+
+    fn parse(&mut self, data: &str, link: Option<&Link>) -> Vec<StyledLine> {
+        let mut lines: Vec<StyledLine> = Vec::new();
+
+        for (i, piece) in data.split('\n').enumerate() {
+            if i > 0 {
+                lines.push(std::mem::replace(&mut self.line, StyledLine::new()));
+            }
+
+            let piece = piece.replace('\r', "").replace('\t', TAB);
+            self.parser.parse(&mut self.handler, piece.as_bytes());
+            self.handler.compact();
+
+            for ansi in self.handler.buf.drain(..) {
+                match ansi {
+                    Ansi::Data(text) => {
+                        self.line
+                            .push(&text, downsample_style(self.style, self.depth), link)
+                    }
+                    Ansi::Command(_, SGR, params, _) => self.style = apply_sgr(self.style, &params),
+                    Ansi::Command(ctl, f, params, _) => {
+                        debug!("ignoring ansi command {:?} {} {:?}", ctl, f, params)
+                    }
+                    Ansi::Esc => (),
+                    Ansi::Error(err, raw) => debug!("invalid ansi sequence {:?} {:?}", err, raw),
+                }
+            }
+        }
+
+        lines
+    }
+
+    /// Parses a chunk of server data, returns the lines it completes
+    pub fn feed(&mut self, data: &str) -> Vec<StyledLine> {
+        self.parse(data, None)
+    }
+
+    /// Parses the text of a link, its spans keep a reference to the link
+    pub fn feed_link(&mut self, link: &Link) -> Vec<StyledLine> {
+        self.parse(&link.text, Some(link))
+    }
+
+    /// The line being received, e.g. a prompt
+    pub fn pending(&self) -> &StyledLine {
+        &self.line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sgr_codes() {
+        let style = apply_sgr(Style::default(), "1;36");
+        assert_eq!(style.fg, Color::Cyan);
+        assert!(style.modifier.contains(Modifier::BOLD));
+
+        let style = apply_sgr(style, "44;4");
+        assert_eq!(style.fg, Color::Cyan);
+        assert_eq!(style.bg, Color::Blue);
+        assert!(style.modifier.contains(Modifier::UNDERLINED));
+
+        let style = apply_sgr(style, "22;39");
+        assert_eq!(style.fg, Color::Reset);
+        assert!(!style.modifier.contains(Modifier::BOLD));
+
+        assert_eq!(apply_sgr(style, ""), Style::default());
+    }
+
+    #[test]
+    fn extended_colors() {
+        let style = apply_sgr(Style::default(), "38;5;208;48;2;10;20;30;1");
+        assert_eq!(style.fg, Color::Indexed(208));
+        assert_eq!(style.bg, Color::Rgb(10, 20, 30));
+        assert!(style.modifier.contains(Modifier::BOLD));
+
+        let style = apply_sgr(style, "38;2;1");
+        assert_eq!(style.fg, Color::Indexed(208));
+    }
+
+    #[test]
+    fn replaces_keeping_styles() {
+        let red = Style::default().fg(Color::Red);
+        let mut line = StyledLine::new();
+        line.push("Bob ", Style::default(), None);
+        line.push("massacres", red, None);
+        line.push(" the rat", Style::default(), None);
+
+        line.replace_range(4, 13, "MASS");
+        assert_eq!(line.text(), "Bob MASS the rat");
+        assert_eq!(line.spans[1].text, "MASS");
+        assert_eq!(line.spans[1].style, red);
+
+        line.replace_range(2, 6, "");
+        assert_eq!(line.text(), "BoSS the rat");
+        line.replace_range(12, 12, "!");
+        assert_eq!(line.text(), "BoSS the rat!");
+    }
+
+    #[test]
+    fn style_carries_over_chunks_and_lines() {
+        let mut parser = StyledParser::new(ColorDepth::TrueColor);
+
+        let lines = parser.feed("OK? [\u{1b}[36my\u{1b}[0mes/\u{1b}[36mn");
+        assert!(lines.is_empty());
+
+        let lines = parser.feed("o\r\nstill cyan\u{1b}[0m\r\n");
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].text(), "OK? [yes/no");
+        assert_eq!(lines[0].spans[1].text, "y");
+        assert_eq!(lines[0].spans[1].style.fg, Color::Cyan);
+        assert_eq!(lines[0].spans[3].text, "no");
+        assert_eq!(lines[1].spans[0].style.fg, Color::Cyan);
+        assert!(parser.pending().is_empty());
+    }
+}
@@ -0,0 +1,241 @@
+use std::env;
+
+use serde::Deserialize;
+use tui::style::{Color, Style};
+
+/// How many colors a terminal can display
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Deserialize)]
+pub enum ColorDepth {
+    #[serde(rename = "16")]
+    Ansi16,
+    #[serde(rename = "256")]
+    Indexed256,
+    #[serde(rename = "truecolor")]
+    TrueColor,
+}
+
+/// How the colors the terminal can't display are approximated
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum Downsampling {
+    #[serde(rename = "nearest-16")]
+    Nearest16,
+    #[serde(rename = "nearest-256")]
+    Nearest256,
+}
+
+impl ColorDepth {
+    /// Guesses the depth of the local terminal from the environment
+    pub fn detect() -> ColorDepth {
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        let term = env::var("TERM").unwrap_or_default();
+
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            ColorDepth::TrueColor
+        } else if term.contains("256color") {
+            ColorDepth::Indexed256
+        } else {
+            ColorDepth::Ansi16
+        }
+    }
+
+    /// Depth the server colors are rendered with on a terminal of this depth
+    pub fn render_depth(self, downsampling: Downsampling) -> ColorDepth {
+        match (self, downsampling) {
+            (ColorDepth::TrueColor, _) => ColorDepth::TrueColor,
+            (ColorDepth::Indexed256, Downsampling::Nearest256) => ColorDepth::Indexed256,
+            _ => ColorDepth::Ansi16,
+        }
+    }
+}
+
+// xterm default values of the 16 ANSI colors
+const ANSI_RGB: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// One of the 16 ANSI colors, 0 to 7 being the normal ones and 8 to 15 the bright ones
+pub fn ansi_color(index: u8) -> Color {
+    match index {
+        0 => Color::Black,
+        1 => Color::Red,
+        2 => Color::Green,
+        3 => Color::Yellow,
+        4 => Color::Blue,
+        5 => Color::Magenta,
+        6 => Color::Cyan,
+        7 => Color::Gray,
+        8 => Color::DarkGray,
+        9 => Color::LightRed,
+        10 => Color::LightGreen,
+        11 => Color::LightYellow,
+        12 => Color::LightBlue,
+        13 => Color::LightMagenta,
+        14 => Color::LightCyan,
+        _ => Color::White,
+    }
+}
+
+/// A color given by its name, its index in the 256 colors palette or as `#rrggbb`
+pub fn parse_color(name: &str) -> Option<Color> {
+    let name = name.to_lowercase();
+    if name.starts_with('#') && name.len() == 7 && name.is_ascii() {
+        let component = |i: usize| u8::from_str_radix(&name[i..i + 2], 16).ok();
+        return Some(Color::Rgb(component(1)?, component(3)?, component(5)?));
+    }
+    if let Ok(index) = name.parse::<u8>() {
+        return Some(if index < 16 { ansi_color(index) } else { Color::Indexed(index) });
+    }
+    let color = match name.as_str() {
+        "reset" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "darkgray" | "darkgrey" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        _ => return None,
+    };
+    Some(color)
+}
+
+/// RGB value of a color of the xterm 256 colors palette
+fn indexed_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => ANSI_RGB[index as usize],
+        16..=231 => {
+            let i = index - 16;
+            (
+                CUBE_LEVELS[(i / 36) as usize],
+                CUBE_LEVELS[((i / 6) % 6) as usize],
+                CUBE_LEVELS[(i % 6) as usize],
+            )
+        }
+        _ => {
+            let level = 8 + 10 * (index - 232);
+            (level, level, level)
+        }
+    }
+}
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+    d(r1, r2) + d(g1, g2) + d(b1, b2)
+}
+
+fn nearest_cube_level(value: u8) -> usize {
+    (0..CUBE_LEVELS.len())
+        .min_by_key(|i| (CUBE_LEVELS[*i] as i32 - value as i32).abs())
+        .unwrap_or(0)
+}
+
+/// Nearest color of the 256 colors palette, the 16 first ones being left out
+/// as their actual value depends on the terminal theme
+pub fn nearest_256(rgb: (u8, u8, u8)) -> u8 {
+    let (r, g, b) = rgb;
+    let cube = 16 + 36 * nearest_cube_level(r) + 6 * nearest_cube_level(g) + nearest_cube_level(b);
+
+    let average = (r as u32 + g as u32 + b as u32) / 3;
+    let gray = 232 + (average.saturating_sub(3) / 10).min(23) as usize;
+
+    if distance(rgb, indexed_rgb(gray as u8)) < distance(rgb, indexed_rgb(cube as u8)) {
+        gray as u8
+    } else {
+        cube as u8
+    }
+}
+
+pub fn nearest_16(rgb: (u8, u8, u8)) -> Color {
+    let index = (0..16u8)
+        .min_by_key(|i| distance(rgb, ANSI_RGB[*i as usize]))
+        .unwrap_or(0);
+    ansi_color(index)
+}
+
+pub fn downsample(color: Color, depth: ColorDepth) -> Color {
+    match (color, depth) {
+        (_, ColorDepth::TrueColor) => color,
+        (Color::Rgb(r, g, b), ColorDepth::Indexed256) => Color::Indexed(nearest_256((r, g, b))),
+        (Color::Rgb(r, g, b), ColorDepth::Ansi16) => nearest_16((r, g, b)),
+        (Color::Indexed(i), ColorDepth::Ansi16) if i < 16 => ansi_color(i),
+        (Color::Indexed(i), ColorDepth::Ansi16) => nearest_16(indexed_rgb(i)),
+        _ => color,
+    }
+}
+
+pub fn downsample_style(style: Style, depth: ColorDepth) -> Style {
+    Style {
+        fg: downsample(style.fg, depth),
+        bg: downsample(style.bg, depth),
+        modifier: style.modifier,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palette() {
+        assert_eq!(indexed_rgb(16), (0, 0, 0));
+        assert_eq!(indexed_rgb(196), (255, 0, 0));
+        assert_eq!(indexed_rgb(231), (255, 255, 255));
+        assert_eq!(indexed_rgb(232), (8, 8, 8));
+        assert_eq!(indexed_rgb(255), (238, 238, 238));
+    }
+
+    #[test]
+    fn nearest_colors() {
+        assert_eq!(nearest_256((255, 0, 0)), 196);
+        assert_eq!(nearest_256((250, 5, 3)), 196);
+        assert_eq!(nearest_256((128, 128, 128)), 244);
+        assert_eq!(nearest_16((250, 5, 3)), Color::LightRed);
+        assert_eq!(nearest_16((0, 0, 10)), Color::Black);
+    }
+
+    #[test]
+    fn color_names() {
+        assert_eq!(parse_color("Green"), Some(Color::Green));
+        assert_eq!(parse_color("9"), Some(Color::LightRed));
+        assert_eq!(parse_color("208"), Some(Color::Indexed(208)));
+        assert_eq!(parse_color("#ff8700"), Some(Color::Rgb(255, 135, 0)));
+        assert_eq!(parse_color("#ff87"), None);
+        assert_eq!(parse_color("mauve"), None);
+    }
+
+    #[test]
+    fn downsampling() {
+        let orange = Color::Rgb(255, 135, 0);
+        assert_eq!(downsample(orange, ColorDepth::TrueColor), orange);
+        assert_eq!(downsample(orange, ColorDepth::Indexed256), Color::Indexed(208));
+        assert_eq!(downsample(Color::Indexed(208), ColorDepth::Indexed256), Color::Indexed(208));
+        assert_eq!(downsample(Color::Indexed(9), ColorDepth::Ansi16), Color::LightRed);
+        assert_eq!(downsample(Color::Cyan, ColorDepth::Ansi16), Color::Cyan);
+    }
+}
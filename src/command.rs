@@ -0,0 +1,98 @@
+/// Splits a line on the separator, a `\` before the separator or another `\` escapes it.
+/// An empty separator keeps the line whole.
+pub fn split(line: &str, separator: &str) -> Vec<String> {
+    if separator.is_empty() {
+        return vec![String::from(line)];
+    }
+
+    let mut commands = Vec::new();
+    let mut current = String::new();
+    let mut rest = line;
+    while !rest.is_empty() {
+        if rest.starts_with('\\') && rest[1..].starts_with(separator) {
+            current.push_str(separator);
+            rest = &rest[1 + separator.len()..];
+        } else if rest.starts_with("\\\\") {
+            current.push('\\');
+            rest = &rest[2..];
+        } else if rest.starts_with(separator) {
+            commands.push(std::mem::replace(&mut current, String::new()));
+            rest = &rest[separator.len()..];
+        } else {
+            let c = rest.chars().next().unwrap();
+            current.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    commands.push(current);
+    commands
+}
+
+/// Count of a repeated command and the command itself: `#5 kill rat` or `5n`.
+/// A command made of digits only, such as a menu choice, is not repeated.
+pub fn repeat(command: &str) -> (usize, &str) {
+    let trimmed = command.trim_start();
+    let explicit = trimmed.starts_with('#');
+    let digits = if explicit { &trimmed[1..] } else { trimmed };
+
+    let len = digits.find(|c: char| !c.is_ascii_digit()).unwrap_or(digits.len());
+    if len == 0 || len == digits.len() {
+        return (1, command);
+    }
+    let rest = &digits[len..];
+    let starts_with_space = rest.starts_with(char::is_whitespace);
+    if explicit != starts_with_space {
+        return (1, command);
+    }
+
+    match digits[..len].parse() {
+        Ok(count) => (count, rest.trim_start()),
+        Err(_) => (1, command),
+    }
+}
+
+/// A command given `count` times up to `max_repeat` when it is repeated, else the command itself
+pub fn repeated(command: &str, max_repeat: usize) -> Vec<String> {
+    let (count, command) = repeat(command);
+    vec![String::from(command); count.min(max_repeat)]
+}
+
+/// Commands to send for a typed line, the repeated ones being given `count` times up to `max_repeat`
+pub fn expand(line: &str, separator: &str, max_repeat: usize) -> Vec<String> {
+    split(line, separator)
+        .iter()
+        .flat_map(|command| repeated(command, max_repeat))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_with_escapes() {
+        assert_eq!(
+            split("get all corpse;sacrifice corpse", ";"),
+            vec!["get all corpse", "sacrifice corpse"]
+        );
+        assert_eq!(split("say hi\\; bye;smile", ";"), vec!["say hi; bye", "smile"]);
+        assert_eq!(split("say \\\\;n", ";"), vec!["say \\", "n"]);
+        assert_eq!(split("say a;;b", ";;"), vec!["say a", "b"]);
+        assert_eq!(split("", ";"), vec![""]);
+        assert_eq!(split("a;b", ""), vec!["a;b"]);
+    }
+
+    #[test]
+    fn repeats() {
+        assert_eq!(repeat("#5 kill rat"), (5, "kill rat"));
+        assert_eq!(repeat("5n"), (5, "n"));
+        assert_eq!(repeat("12north"), (12, "north"));
+        assert_eq!(repeat("3"), (1, "3"));
+        assert_eq!(repeat("#5kill"), (1, "#5kill"));
+        assert_eq!(repeat("2 apples"), (1, "2 apples"));
+        assert_eq!(repeat("look"), (1, "look"));
+
+        assert_eq!(expand("2n;#2 e;look", ";", 100), vec!["n", "n", "e", "e", "look"]);
+        assert_eq!(expand("500n", ";", 3).len(), 3);
+    }
+}
@@ -0,0 +1,305 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use log::{debug, warn};
+use mudnet::encoder::Charset;
+use mudnet::mtts::{self, Features};
+use mudnet::MudConfig;
+use serde::Deserialize;
+
+use crate::color::{ColorDepth, Downsampling};
+use crate::APP_NAME;
+
+const CONFIG_FILE: &'static str = "config.toml";
+const WORLDS_DIR: &'static str = "worlds";
+
+/// Directory holding the client configuration, e.g. `~/.config/mud-client`
+pub fn config_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join(APP_NAME)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub world: WorldConfig,
+    pub protocols: ProtocolConfig,
+    pub sound: SoundConfig,
+    pub links: LinkConfig,
+    pub colors: ColorConfig,
+    pub ui: UiConfig,
+    pub commands: CommandConfig,
+    pub speedwalk: SpeedwalkConfig,
+}
+
+/// The MUD connected to
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct WorldConfig {
+    /// name of the directory keeping the data of the world, `<host>_<port>` when not set
+    pub name: Option<String>,
+    pub host: String,
+    pub port: u16,
+}
+
+/// Optional telnet protocols, negotiated only when enabled
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ProtocolConfig {
+    /// "UTF-8" or "ISO-8859-1"
+    pub charset: String,
+    pub binary: bool,
+    /// seconds between two keepalives, 0 to disable them
+    pub keepalive: u64,
+    pub msp: bool,
+    pub atcp: bool,
+    pub aardwolf102: bool,
+    pub msdp: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SoundConfig {
+    /// Where the MSP sound and music files are looked up
+    pub media_dir: PathBuf,
+    /// Command run to play a file, e.g. `["mpv", "--volume={volume}", "{file}"]`.
+    /// Available placeholders: {file}, {volume}, {loops}, {priority}, {type}, {url}
+    pub command: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LinkConfig {
+    /// Command run to open the MSLP links pointing to an url, the url replaces {url}
+    pub url_opener: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ColorConfig {
+    /// "16", "256" or "truecolor", detected from COLORTERM and TERM when not set
+    pub terminal: Option<ColorDepth>,
+    /// "nearest-16" or "nearest-256", how the colors beyond the terminal depth are approximated
+    pub downsampling: Downsampling,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct UiConfig {
+    /// number of lines kept in the Main area
+    pub scrollback: usize,
+    /// percentage of the Main area following the output while scrolled back, 0 to disable it
+    pub split: u16,
+    /// columns the continuation rows of the wrapped lines are indented with
+    pub indent: usize,
+    /// number of commands kept in the history
+    pub history: usize,
+    /// the server lines, gagged ones included, are written to the `raw` log target
+    pub raw_log: bool,
+}
+
+/// How the typed lines are turned into commands
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CommandConfig {
+    /// starts the commands of the client, doubled to send a line starting with it
+    pub prefix: String,
+    /// separates the commands of a line, escaped with a `\`, empty to send the lines as typed.
+    /// A line starting with the prefix is a single client command.
+    pub separator: String,
+    /// highest count of a repeated command, e.g. `#5 kill rat` or `5n`
+    pub max_repeat: usize,
+}
+
+/// Movements typed as `3n2e4s` or `#walk 3n 2e`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SpeedwalkConfig {
+    /// the directions of the walks and the commands they send, e.g. `u = "up"`
+    pub directions: BTreeMap<String, String>,
+    /// seconds between two steps, 0 to send them at once
+    pub delay: f64,
+    /// line stopping a delayed walk, a regex between slashes or a text found anywhere in the line
+    pub failure: String,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            world: WorldConfig::default(),
+            protocols: ProtocolConfig::default(),
+            sound: SoundConfig::default(),
+            links: LinkConfig::default(),
+            colors: ColorConfig::default(),
+            ui: UiConfig::default(),
+            commands: CommandConfig::default(),
+            speedwalk: SpeedwalkConfig::default(),
+        }
+    }
+}
+
+impl Default for WorldConfig {
+    fn default() -> WorldConfig {
+        WorldConfig {
+            name: None,
+            host: String::from("localhost"),
+            port: 9696,
+        }
+    }
+}
+
+impl WorldConfig {
+    /// `<host>:<port>`, to connect to
+    pub fn address(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    pub fn name(&self) -> String {
+        self.name
+            .clone()
+            .unwrap_or_else(|| format!("{}_{}", self.host, self.port))
+    }
+
+    /// Directory keeping the data of the world, e.g. `~/.config/mud-client/worlds/aardwolf`
+    pub fn dir(&self) -> PathBuf {
+        config_dir().join(WORLDS_DIR).join(self.name())
+    }
+}
+
+impl Default for ProtocolConfig {
+    fn default() -> ProtocolConfig {
+        ProtocolConfig {
+            charset: String::from(Charset::Utf8.name()),
+            binary: false,
+            keepalive: 60,
+            msp: true,
+            atcp: false,
+            aardwolf102: false,
+            msdp: true,
+        }
+    }
+}
+
+impl Default for SoundConfig {
+    fn default() -> SoundConfig {
+        SoundConfig {
+            media_dir: config_dir().join("media"),
+            command: Vec::new(),
+        }
+    }
+}
+
+impl Default for LinkConfig {
+    fn default() -> LinkConfig {
+        LinkConfig {
+            url_opener: vec![String::from("xdg-open"), String::from("{url}")],
+        }
+    }
+}
+
+impl Default for ColorConfig {
+    fn default() -> ColorConfig {
+        ColorConfig {
+            terminal: None,
+            downsampling: Downsampling::Nearest256,
+        }
+    }
+}
+
+impl Default for UiConfig {
+    fn default() -> UiConfig {
+        UiConfig {
+            scrollback: 10_000,
+            split: 30,
+            indent: 0,
+            history: 1000,
+            raw_log: false,
+        }
+    }
+}
+
+impl Default for CommandConfig {
+    fn default() -> CommandConfig {
+        CommandConfig {
+            prefix: String::from(":"),
+            separator: String::from(";"),
+            max_repeat: 100,
+        }
+    }
+}
+
+impl Default for SpeedwalkConfig {
+    fn default() -> SpeedwalkConfig {
+        let directions = [
+            "n", "s", "e", "w", "ne", "nw", "se", "sw", "u", "d", "in", "out",
+        ];
+        SpeedwalkConfig {
+            directions: directions
+                .iter()
+                .map(|d| (String::from(*d), String::from(*d)))
+                .collect(),
+            delay: 0.0,
+            failure: String::from("You can't go that way"),
+        }
+    }
+}
+
+impl ColorConfig {
+    pub fn terminal_depth(&self) -> ColorDepth {
+        self.terminal.unwrap_or_else(ColorDepth::detect)
+    }
+
+    /// Depth the server colors are rendered with
+    pub fn render_depth(&self) -> ColorDepth {
+        self.terminal_depth().render_depth(self.downsampling)
+    }
+}
+
+impl Config {
+    /// Reads `config.toml` from the configuration directory, falling back to the defaults
+    pub fn load() -> Config {
+        let path = config_dir().join(CONFIG_FILE);
+
+        match fs::read_to_string(&path) {
+            Ok(content) => toml::from_str(&content).unwrap_or_else(|e| {
+                warn!("invalid configuration {:?} : {}", path, e);
+                Config::default()
+            }),
+            Err(e) => {
+                debug!("no configuration read from {:?} : {}", path, e);
+                Config::default()
+            }
+        }
+    }
+
+    pub fn mud_config(&self) -> MudConfig {
+        let mut config = MudConfig::default();
+        config.charset = Charset::from_name(&self.protocols.charset).unwrap_or_else(|| {
+            warn!("unknown charset {}, using UTF-8", self.protocols.charset);
+            Charset::Utf8
+        });
+        // as told by the CHARSET variable of MNES
+        config.features.set(Features::UTF8, config.charset == Charset::Utf8);
+        config.binary = self.protocols.binary;
+        config.keepalive = match self.protocols.keepalive {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        };
+        config.msp = self.protocols.msp;
+        config.atcp = self.protocols.atcp;
+        config.aardwolf102 = self.protocols.aardwolf102;
+        config.msdp = self.protocols.msdp;
+
+        // the server is told the colors rendered, after the downsampling
+        match self.colors.render_depth() {
+            ColorDepth::TrueColor => config.features |= Features::_256COLORS | Features::TRUECOLOR,
+            ColorDepth::Indexed256 => config.features |= Features::_256COLORS,
+            // XTERM would imply 256 colors
+            ColorDepth::Ansi16 => config.terminal_type = mtts::terminal_type::ANSI,
+        }
+        config
+    }
+}
@@ -0,0 +1,207 @@
+use log::warn;
+use regex::{Regex, RegexSet};
+
+use crate::alias;
+use crate::ansi::StyledLine;
+use crate::trigger::regex_source;
+use crate::world::{GagConfig, SubstitutionConfig};
+
+/// Hides the server lines matching a pattern
+pub struct Gag {
+    pub config: GagConfig,
+    regex: Regex,
+}
+
+/// Rewrites the text matching a pattern in the server lines
+pub struct Substitution {
+    pub config: SubstitutionConfig,
+    regex: Regex,
+}
+
+impl Gag {
+    pub fn new(config: GagConfig) -> Result<Gag, regex::Error> {
+        let regex = Regex::new(&regex_source(&config.pattern))?;
+        Ok(Gag { config, regex })
+    }
+}
+
+impl Substitution {
+    pub fn new(config: SubstitutionConfig) -> Result<Substitution, regex::Error> {
+        let regex = Regex::new(&regex_source(&config.pattern))?;
+        Ok(Substitution { config, regex })
+    }
+}
+
+/// Gags and substitutions applied to the server lines before they reach the scrollback
+pub struct Rewrites {
+    gags: Vec<Gag>,
+    gag_set: RegexSet,
+    substitutions: Vec<Substitution>,
+}
+
+impl Default for Rewrites {
+    fn default() -> Rewrites {
+        Rewrites {
+            gags: Vec::new(),
+            gag_set: RegexSet::empty(),
+            substitutions: Vec::new(),
+        }
+    }
+}
+
+impl Rewrites {
+    /// Compiles the saved gags and substitutions, the invalid ones being skipped
+    pub fn from_config(gags: &[GagConfig], substitutions: &[SubstitutionConfig]) -> Rewrites {
+        let mut rewrites = Rewrites::default();
+        for config in gags {
+            match Gag::new(config.clone()) {
+                Ok(gag) => rewrites.gags.push(gag),
+                Err(e) => warn!("invalid gag {} : {}", config.pattern, e),
+            }
+        }
+        for config in substitutions {
+            match Substitution::new(config.clone()) {
+                Ok(substitution) => rewrites.substitutions.push(substitution),
+                Err(e) => warn!("invalid substitution {} : {}", config.pattern, e),
+            }
+        }
+        rewrites.compile();
+        rewrites
+    }
+
+    pub fn to_gag_config(&self) -> Vec<GagConfig> {
+        self.gags.iter().map(|g| g.config.clone()).collect()
+    }
+
+    pub fn to_substitution_config(&self) -> Vec<SubstitutionConfig> {
+        self.substitutions.iter().map(|s| s.config.clone()).collect()
+    }
+
+    fn compile(&mut self) {
+        let sources = self.gags.iter().map(|g| g.regex.as_str());
+        // the regexes were all compiled already
+        self.gag_set = RegexSet::new(sources).unwrap_or_else(|_| RegexSet::empty());
+    }
+
+    /// Adds a gag, replacing the one with the same pattern
+    pub fn set_gag(&mut self, gag: Gag) {
+        match self.gags.iter_mut().find(|g| g.config.pattern == gag.config.pattern) {
+            Some(existing) => *existing = gag,
+            None => self.gags.push(gag),
+        }
+        self.compile();
+    }
+
+    /// Adds a substitution, replacing the one with the same pattern
+    pub fn set_substitution(&mut self, substitution: Substitution) {
+        let pattern = &substitution.config.pattern;
+        match self.substitutions.iter_mut().find(|s| &s.config.pattern == pattern) {
+            Some(existing) => *existing = substitution,
+            None => self.substitutions.push(substitution),
+        }
+    }
+
+    pub fn remove_gag(&mut self, pattern: &str) -> bool {
+        let len = self.gags.len();
+        self.gags.retain(|g| g.config.pattern != pattern);
+        self.compile();
+        self.gags.len() != len
+    }
+
+    pub fn remove_substitution(&mut self, pattern: &str) -> bool {
+        let len = self.substitutions.len();
+        self.substitutions.retain(|s| s.config.pattern != pattern);
+        self.substitutions.len() != len
+    }
+
+    pub fn gags(&self) -> impl Iterator<Item = &Gag> {
+        self.gags.iter()
+    }
+
+    pub fn substitutions(&self) -> impl Iterator<Item = &Substitution> {
+        self.substitutions.iter()
+    }
+
+    /// Whether a gag of an enabled group matches the text of a line
+    pub fn is_gagged<F>(&self, text: &str, enabled: F) -> bool
+    where
+        F: Fn(&str) -> bool,
+    {
+        self.gag_set
+            .matches(text)
+            .into_iter()
+            .any(|i| enabled(&self.gags[i].config.group))
+    }
+
+    /// Applies the substitutions of the enabled groups in turn, the replacements keeping the style of the text replaced
+    pub fn substitute<F>(&self, line: &mut StyledLine, enabled: F)
+    where
+        F: Fn(&str) -> bool,
+    {
+        for substitution in self.substitutions.iter() {
+            if !enabled(&substitution.config.group) {
+                continue;
+            }
+            let text = line.text();
+            let replacements: Vec<(usize, usize, String)> = substitution
+                .regex
+                .captures_iter(&text)
+                .map(|captures| {
+                    let m = captures.get(0).unwrap();
+                    let (start, end) = (m.start(), m.end());
+                    let replacement =
+                        alias::substitute_captures(&substitution.config.replacement, captures);
+                    (start, end, replacement)
+                })
+                .collect();
+            // from the end, so the offsets of the previous matches stay valid
+            for (start, end, replacement) in replacements.iter().rev() {
+                line.replace_range(*start, *end, replacement);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tui::style::{Color, Style};
+
+    fn rewrites() -> Rewrites {
+        let gag = |pattern: &str, group: &str| GagConfig {
+            pattern: String::from(pattern),
+            group: String::from(group),
+        };
+        let substitution = |pattern: &str, replacement: &str| SubstitutionConfig {
+            pattern: String::from(pattern),
+            replacement: String::from(replacement),
+            group: String::new(),
+        };
+        Rewrites::from_config(
+            &[gag("/^\\[Gossip\\]/", "channels"), gag("misses", "")],
+            &[substitution("/(\\w+) massacres/", "%1 MASS"), substitution("the", "a")],
+        )
+    }
+
+    #[test]
+    fn gags_by_group() {
+        let rewrites = rewrites();
+        assert!(rewrites.is_gagged("[Gossip] Bob: hi", |_| true));
+        assert!(!rewrites.is_gagged("[Gossip] Bob: hi", |group| group != "channels"));
+        assert!(rewrites.is_gagged("Bob misses you.", |group| group != "channels"));
+        assert!(!rewrites.is_gagged("Bob hits you.", |_| true));
+    }
+
+    #[test]
+    fn substitutes_keeping_styles() {
+        let red = Style::default().fg(Color::Red);
+        let mut line = StyledLine::new();
+        line.push("Bob massacres", red, None);
+        line.push(" the rat and the cat", Style::default(), None);
+
+        rewrites().substitute(&mut line, |_| true);
+        assert_eq!(line.text(), "Bob MASS a rat and a cat");
+        assert_eq!(line.spans[0].text, "Bob MASS");
+        assert_eq!(line.spans[0].style, red);
+    }
+}
@@ -0,0 +1,177 @@
+use log::warn;
+use regex::Regex;
+use tui::style::{Color, Modifier, Style};
+
+use crate::color::parse_color;
+use crate::trigger::regex_source;
+use crate::world::HighlightConfig;
+
+/// Colors the text matching a pattern or one of a list of words when the lines are displayed
+pub struct Highlight {
+    pub config: HighlightConfig,
+    regex: Regex,
+    /// the `Reset` colors leave the colors of the server
+    style: Style,
+}
+
+/// Parses `<fg>[/<bg>][+bold][+underline][+italic][+reverse]`,
+/// e.g. `green`, `black/yellow+bold` or `/#202020`
+pub fn parse_style(spec: &str) -> Result<Style, String> {
+    let mut parts = spec.split('+');
+    let mut style = Style::default();
+
+    let colors = parts.next().unwrap_or("");
+    let mut colors = colors.splitn(2, '/');
+    let color = |name: Option<&str>| -> Result<Color, String> {
+        match name {
+            None | Some("") => Ok(Color::Reset),
+            Some(name) => parse_color(name).ok_or_else(|| format!("unknown color {}", name)),
+        }
+    };
+    style = style.fg(color(colors.next())?).bg(color(colors.next())?);
+
+    for modifier in parts {
+        let modifier = match modifier {
+            "bold" => Modifier::BOLD,
+            "underline" => Modifier::UNDERLINED,
+            "italic" => Modifier::ITALIC,
+            "reverse" => Modifier::REVERSED,
+            _ => return Err(format!("unknown modifier {}", modifier)),
+        };
+        style = style.modifier(style.modifier | modifier);
+    }
+    Ok(style)
+}
+
+impl Highlight {
+    pub fn new(config: HighlightConfig) -> Result<Highlight, String> {
+        if config.pattern.is_empty() && config.words.is_empty() {
+            return Err(String::from("no pattern"));
+        }
+        let source = if config.words.is_empty() {
+            regex_source(&config.pattern)
+        } else {
+            let words: Vec<String> = config.words.iter().map(|w| regex::escape(w)).collect();
+            format!(r"\b(?:{})\b", words.join("|"))
+        };
+        let regex = Regex::new(&source).map_err(|e| e.to_string())?;
+        let style = parse_style(&config.style)?;
+        Ok(Highlight {
+            config,
+            regex,
+            style,
+        })
+    }
+
+    /// The style of the text under the highlight
+    pub fn apply(&self, style: Style) -> Style {
+        Style {
+            fg: if self.style.fg == Color::Reset { style.fg } else { self.style.fg },
+            bg: if self.style.bg == Color::Reset { style.bg } else { self.style.bg },
+            modifier: style.modifier | self.style.modifier,
+        }
+    }
+}
+
+/// The highlights of the world, applied in the order they were defined
+#[derive(Default)]
+pub struct Highlights {
+    highlights: Vec<Highlight>,
+}
+
+impl Highlights {
+    /// Compiles the saved highlights, the invalid ones being skipped
+    pub fn from_config(configs: &[HighlightConfig]) -> Highlights {
+        let mut highlights = Highlights::default();
+        for config in configs {
+            match Highlight::new(config.clone()) {
+                Ok(highlight) => highlights.highlights.push(highlight),
+                Err(e) => warn!("invalid highlight {} : {}", config.key(), e),
+            }
+        }
+        highlights
+    }
+
+    pub fn to_config(&self) -> Vec<HighlightConfig> {
+        self.highlights.iter().map(|h| h.config.clone()).collect()
+    }
+
+    /// Adds a highlight, replacing the one with the same pattern or words
+    pub fn set(&mut self, highlight: Highlight) {
+        let key = highlight.config.key();
+        match self.highlights.iter_mut().find(|h| h.config.key() == key) {
+            Some(existing) => *existing = highlight,
+            None => self.highlights.push(highlight),
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) -> bool {
+        let len = self.highlights.len();
+        self.highlights.retain(|h| h.config.key() != key);
+        self.highlights.len() != len
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Highlight> {
+        self.highlights.iter()
+    }
+
+    /// Byte ranges of the text to highlight, with their highlight
+    pub fn ranges<F>(&self, text: &str, enabled: F) -> Vec<(usize, usize, &Highlight)>
+    where
+        F: Fn(&str) -> bool,
+    {
+        let mut ranges = Vec::new();
+        for highlight in self.highlights.iter() {
+            if enabled(&highlight.config.group) {
+                for m in highlight.regex.find_iter(text) {
+                    ranges.push((m.start(), m.end(), highlight));
+                }
+            }
+        }
+        ranges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn highlight(pattern: &str, words: &[&str], style: &str) -> Highlight {
+        Highlight::new(HighlightConfig {
+            pattern: String::from(pattern),
+            words: words.iter().map(|w| String::from(*w)).collect(),
+            style: String::from(style),
+            group: String::new(),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn parses_styles() {
+        let style = parse_style("black/yellow+bold").unwrap();
+        assert_eq!(style.fg, Color::Black);
+        assert_eq!(style.bg, Color::Yellow);
+        assert!(style.modifier.contains(Modifier::BOLD));
+
+        assert_eq!(parse_style("/#202020").unwrap().bg, Color::Rgb(32, 32, 32));
+        assert_eq!(parse_style("green").unwrap().bg, Color::Reset);
+        assert!(parse_style("green+blink").is_err());
+    }
+
+    #[test]
+    fn highlights_on_top_of_server_colors() {
+        let mut highlights = Highlights::default();
+        highlights.set(highlight("", &["Bob", "Al"], "green"));
+        highlights.set(highlight("You are hungry", &[], "red+bold"));
+
+        let ranges = highlights.ranges("Bob and Alice see Al. You are hungry.", |_| true);
+        let found: Vec<(usize, usize)> = ranges.iter().map(|(s, e, _)| (*s, *e)).collect();
+        assert_eq!(found, vec![(0, 3), (18, 20), (22, 36)]);
+
+        let server = Style::default().fg(Color::Blue).bg(Color::White);
+        let style = ranges[2].2.apply(server);
+        assert_eq!(style.fg, Color::Red);
+        assert_eq!(style.bg, Color::White);
+        assert!(style.modifier.contains(Modifier::BOLD));
+    }
+}
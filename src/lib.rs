@@ -1,4 +1,28 @@
+pub mod alias;
+pub mod ansi;
+pub mod color;
+pub mod command;
+pub mod config;
+pub mod gag;
+pub mod highlight;
 pub mod mud;
+pub mod script;
+pub mod session;
+pub mod sound;
+pub mod speedwalk;
+pub mod timer;
+pub mod trigger;
 pub mod ui;
+pub mod variable;
+pub mod world;
 
 pub const APP_NAME: &'static str = "mud-client";
+
+/// An empty directory for a test, unique to the test and the run, to remove once done
+#[cfg(test)]
+pub fn test_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("{}-{}-{}", APP_NAME, name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -1,17 +1,19 @@
-use std::process;
 use std::{
     io::{self, stdout, Write},
     time::Duration,
 };
 
 use crossterm::{
-    event::{self, Event as CEvent, EventStream, KeyCode, KeyEvent},
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event as CEvent, EventStream, KeyCode,
+        KeyEvent,
+    },
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 
 use futures::Future;
-use log::{debug, error, warn};
+use log::{debug, warn};
 use telnet::{Telnet, TelnetOption, TelnetWriter};
 use tokio::prelude::*;
 use tokio::sync::mpsc::{self, error::TryRecvError, Receiver, Sender};
@@ -24,11 +26,12 @@ use tui::symbols::DOT;
 use tui::widgets::{Block, BorderType, Borders, Tabs, Widget};
 use tui::Terminal;
 
+use mct::config::Config;
 use mct::ui;
 use mct::ui::app::App;
 use mct::ui::app_events;
 use mct::ui::events::{Event, Events};
-use mudnet::{self, CnxOutput};
+use mudnet::{self, CnxInput, CnxOutput};
 use std::fs::read;
 
 /*
@@ -55,57 +58,73 @@ async fn main() -> Result<(), failure::Error> {
     enable_raw_mode()?;
 
     let mut stdout = stdout();
-    execute!(stdout, EnterAlternateScreen)?;
+    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
 
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
-    let mut app = App::new();
+    let config = Config::load();
+    let mut app = App::new(config);
 
-    let (mut command_sender, command_receiver): (Sender<String>, Receiver<String>) =
-        mpsc::channel(100);
+    // closed until the connection is opened, the commands telling they are not sent
+    let (mut command_sender, _): (Sender<CnxInput>, Receiver<CnxInput>) = mpsc::channel(1);
     let (cnx_sender, cnx_receiver): (Sender<CnxOutput>, Receiver<CnxOutput>) = mpsc::channel(100);
 
     //let host = ("edge.xen.prgmr.com",4000);
     //RcSmxqq6&
     //aardwolf.org (23.111.136.202) port 4000
     //let host = "aardwolf.org:4000";
-    let host = ("localhost", 9696); //currymud
-                                    //let host = ("localhost", 27733);
-    let tcp_stream = Box::new(tokio::net::TcpStream::connect(host).await.unwrap_or_else(
-        |_| -> tokio::net::TcpStream {
-            error!("failed to establish connection with {:?}", host);
-            process::exit(1);
-        },
-    ));
-
-    tokio::spawn(mudnet::handler(tcp_stream, command_receiver, cnx_sender));
+    //let host = ("localhost", 9696); //currymud
+    //let host = ("localhost", 27733);
+    app.connection_request = Some(app.config.world.address());
 
     let mut events = Events::new(cnx_receiver);
+    // q is typed in commands and search patterns
+    events.disable_exit_key();
 
     loop {
-        ui::draw_app(&mut terminal, &app)?;
+        app_events::connect(&mut app, &mut command_sender, &cnx_sender);
+        ui::draw_app(&mut terminal, &mut app)?;
 
         match events.next().await {
-            Some(Event::Input(CEvent::Key(KeyEvent {
-                code: KeyCode::Esc,
-                modifiers: _,
-            }))) => {
-                break;
-            }
             Some(Event::Input(CEvent::Key(key_event))) => {
                 if app_events::handle_key_event(&mut app, &mut command_sender, key_event).await {
                     break;
                 }
             }
+            Some(Event::Input(CEvent::Mouse(mouse_event))) => {
+                if app_events::handle_mouse_event(&mut app, &mut command_sender, mouse_event).await
+                {
+                    break;
+                }
+            }
             Some(Event::Input(_)) => {}
-            Some(Event::Tick) => {}
-            Some(Event::Network(msg)) => app.apply_event(msg),
+            Some(Event::Tick) => {
+                app.reload_scripts(false);
+                app.run_timers();
+                if app_events::send_pending_commands(&mut app, &mut command_sender).await {
+                    break;
+                }
+            }
+            Some(Event::Network(msg)) => {
+                app.apply_event(msg);
+                if app_events::send_pending_commands(&mut app, &mut command_sender).await {
+                    break;
+                }
+            }
 
             None => {}
         }
     }
 
-    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    if let Err(e) = app.history.save() {
+        warn!("failed to save the history : {}", e);
+    }
+
+    execute!(
+        terminal.backend_mut(),
+        LeaveAlternateScreen,
+        DisableMouseCapture
+    )?;
     disable_raw_mode()?;
     Ok(())
 }
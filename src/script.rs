@@ -0,0 +1,454 @@
+use std::cell::RefCell;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime};
+
+use mudnet::msdp::MsdpVal;
+use rhai::{Array, CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope, AST, FLOAT, INT};
+
+use crate::session::SharedSession;
+use crate::timer::check_period;
+use crate::variable;
+
+/// Directory of the world directory holding the `.rhai` scripts
+pub const SCRIPTS_DIR: &'static str = "scripts";
+
+const EXTENSION: &'static str = "rhai";
+
+/// Most operations run by a call, an endless loop failing instead of freezing the client
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// Largest string, in bytes, and largest array and map a script builds, failing beyond them
+/// instead of running out of memory
+const MAX_STRING_SIZE: usize = 1 << 20;
+const MAX_COLLECTION_SIZE: usize = 100_000;
+
+/// Time between two looks for changed script files
+const RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Function of the scripts called with the text of each server line
+const ON_LINE: &'static str = "on_line";
+
+/// What the scripts ask the client to do, applied once they return
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptAction {
+    /// a command, expanded like a typed one
+    Send(String),
+    Echo(String),
+    Alias {
+        pattern: String,
+        body: String,
+    },
+    Trigger {
+        pattern: String,
+        action: String,
+    },
+    Timer {
+        name: String,
+        seconds: f64,
+        command: String,
+    },
+    /// the scripts are run again, the aliases, triggers and timers they defined being dropped
+    Reset,
+}
+
+/// A script file, its top level statements being run when it is loaded
+struct Script {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    /// empty when the file does not compile
+    ast: AST,
+    /// the variables of the top level
+    scope: Scope<'static>,
+}
+
+impl Script {
+    fn defines(&self, name: &str, params: usize) -> bool {
+        self.ast
+            .iter_functions()
+            .any(|f| f.name == name && f.params.len() == params)
+    }
+
+    fn call(&mut self, engine: &Engine, name: &str, args: Vec<Dynamic>) -> Result<(), String> {
+        // the top level statements only run when the file is loaded
+        let options = CallFnOptions::new().eval_ast(false);
+        engine
+            .call_fn_with_options::<Dynamic>(options, &mut self.scope, &self.ast, name, args)
+            .map(|_| ())
+            .map_err(|e| format!("{} : {}", self.path.display(), e))
+    }
+}
+
+fn msdp_value(value: &MsdpVal) -> Dynamic {
+    match value {
+        MsdpVal::Value(value) => Dynamic::from(value.clone()),
+        MsdpVal::Array(values) => Dynamic::from(values.iter().map(msdp_value).collect::<Array>()),
+        MsdpVal::Table(entries) => Dynamic::from(
+            entries
+                .iter()
+                .map(|(key, value)| (key.as_str().into(), msdp_value(value)))
+                .collect::<Map>(),
+        ),
+    }
+}
+
+/// The functions the scripts call: `send`, `echo`, `alias`, `trigger`, `timer`,
+/// `get_var`, `set_var`, `msdp`, `gmcp`, `atcp` and `latency` in milliseconds, `print` echoing
+/// its text
+fn engine(session: &SharedSession, actions: &Rc<RefCell<Vec<ScriptAction>>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_string_size(MAX_STRING_SIZE);
+    engine.set_max_array_size(MAX_COLLECTION_SIZE);
+    engine.set_max_map_size(MAX_COLLECTION_SIZE);
+
+    let queue = actions.clone();
+    engine.on_print(move |text| {
+        queue
+            .borrow_mut()
+            .push(ScriptAction::Echo(String::from(text)))
+    });
+    let queue = actions.clone();
+    engine.register_fn("echo", move |text: &str| {
+        queue
+            .borrow_mut()
+            .push(ScriptAction::Echo(String::from(text)))
+    });
+    let queue = actions.clone();
+    engine.register_fn("send", move |command: &str| {
+        queue
+            .borrow_mut()
+            .push(ScriptAction::Send(String::from(command)))
+    });
+    let queue = actions.clone();
+    engine.register_fn("alias", move |pattern: &str, body: &str| {
+        queue.borrow_mut().push(ScriptAction::Alias {
+            pattern: String::from(pattern),
+            body: String::from(body),
+        })
+    });
+    let queue = actions.clone();
+    engine.register_fn("trigger", move |pattern: &str, action: &str| {
+        queue.borrow_mut().push(ScriptAction::Trigger {
+            pattern: String::from(pattern),
+            action: String::from(action),
+        })
+    });
+    let queue = actions.clone();
+    let timer = move |name: &str, seconds: f64, command: &str| -> Result<(), Box<EvalAltResult>> {
+        queue.borrow_mut().push(ScriptAction::Timer {
+            name: String::from(name),
+            seconds: check_period(seconds)?,
+            command: String::from(command),
+        });
+        Ok(())
+    };
+    let seconds = timer.clone();
+    engine.register_fn("timer", move |name: &str, s: FLOAT, command: &str| {
+        timer(name, s, command)
+    });
+    engine.register_fn("timer", move |name: &str, s: INT, command: &str| {
+        seconds(name, s as f64, command)
+    });
+
+    let shared = session.clone();
+    engine.register_fn("get_var", move |name: &str| -> Dynamic {
+        match shared.borrow().variable(name) {
+            Some(value) => Dynamic::from(value),
+            None => Dynamic::UNIT,
+        }
+    });
+    let shared = session.clone();
+    engine.register_fn(
+        "set_var",
+        move |name: &str, value: Dynamic| -> Result<(), Box<EvalAltResult>> {
+            if !variable::is_valid_name(name) {
+                return Err(format!("invalid variable name {}", name).into());
+            }
+            let value = value.to_string();
+            shared
+                .borrow_mut()
+                .variables
+                .insert(String::from(name), value);
+            Ok(())
+        },
+    );
+    let shared = session.clone();
+    engine.register_fn("msdp", move |key: &str| -> Dynamic {
+        shared
+            .borrow()
+            .msdp
+            .get(key)
+            .map_or(Dynamic::UNIT, msdp_value)
+    });
+    let shared = session.clone();
+    engine.register_fn("gmcp", move |package: &str| -> Dynamic {
+        let session = shared.borrow();
+        match session.gmcp.get(package) {
+            Some(data) => Dynamic::from(data.clone()),
+            None => Dynamic::UNIT,
+        }
+    });
+    let shared = session.clone();
+    engine.register_fn("atcp", move |name: &str| -> Dynamic {
+        let session = shared.borrow();
+        match session.atcp.get(name) {
+            Some(value) => Dynamic::from(value.clone()),
+            None => Dynamic::UNIT,
+        }
+    });
+    let shared = session.clone();
+    engine.register_fn("latency", move || -> Dynamic {
+        match shared.borrow().latency {
+            Some(latency) => Dynamic::from(latency.as_millis() as INT),
+            None => Dynamic::UNIT,
+        }
+    });
+    engine
+}
+
+/// The Rhai scripts of the world directory, reloaded when their files change
+pub struct Scripts {
+    engine: Engine,
+    dir: PathBuf,
+    scripts: Vec<Script>,
+    actions: Rc<RefCell<Vec<ScriptAction>>>,
+    last_reload: Option<Instant>,
+}
+
+impl Scripts {
+    pub fn new(dir: PathBuf, session: &SharedSession) -> Scripts {
+        let actions = Rc::new(RefCell::new(Vec::new()));
+        Scripts {
+            engine: engine(session, &actions),
+            dir,
+            scripts: Vec::new(),
+            actions,
+            last_reload: None,
+        }
+    }
+
+    /// Paths of the loaded scripts
+    pub fn iter(&self) -> impl Iterator<Item = &Path> {
+        self.scripts.iter().map(|s| s.path.as_path())
+    }
+
+    /// Runs all the scripts again when one of them was added, changed or removed, or when forced,
+    /// what they define being rebuilt. Returns the errors.
+    pub fn reload(&mut self, now: Instant, force: bool) -> Vec<String> {
+        let recent = match self.last_reload {
+            Some(last) => now < last + RELOAD_INTERVAL,
+            None => false,
+        };
+        if recent && !force {
+            return Vec::new();
+        }
+        self.last_reload = Some(now);
+
+        // a world without scripts has no scripts directory
+        let mut files: Vec<(PathBuf, Option<SystemTime>)> = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .filter(|path| path.extension() == Some(OsStr::new(EXTENSION)))
+                .map(|path| {
+                    let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+                    (path, modified)
+                })
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        files.sort();
+        let unchanged = files.len() == self.scripts.len()
+            && files
+                .iter()
+                .zip(self.scripts.iter())
+                .all(|((path, modified), s)| s.path == *path && s.modified == *modified);
+        if unchanged && !force {
+            return Vec::new();
+        }
+
+        self.scripts.clear();
+        self.actions.borrow_mut().push(ScriptAction::Reset);
+        let mut errors = Vec::new();
+        for (path, modified) in files {
+            let loaded = fs::read_to_string(&path)
+                .map_err(|e| format!("{} : {}", path.display(), e))
+                .and_then(|source| self.load(path, modified, &source));
+            if let Err(e) = loaded {
+                errors.push(e);
+            }
+        }
+        errors
+    }
+
+    /// Compiles a script and runs its top level, replacing the script loaded from the same path.
+    /// A script failing to compile is kept empty, not to report its error again until it changes.
+    fn load(
+        &mut self,
+        path: PathBuf,
+        modified: Option<SystemTime>,
+        source: &str,
+    ) -> Result<(), String> {
+        let mut script = Script {
+            path,
+            modified,
+            ast: AST::empty(),
+            scope: Scope::new(),
+        };
+        let result = match self.engine.compile(source) {
+            Ok(ast) => {
+                script.ast = ast;
+                self.engine
+                    .run_ast_with_scope(&mut script.scope, &script.ast)
+                    .map_err(|e| e.to_string())
+            }
+            Err(e) => Err(e.to_string()),
+        };
+        let result = result.map_err(|e| format!("{} : {}", script.path.display(), e));
+
+        match self.scripts.iter_mut().find(|s| s.path == script.path) {
+            Some(existing) => *existing = script,
+            None => self.scripts.push(script),
+        }
+        result
+    }
+
+    /// Calls a function of the first script defining it with as many parameters as arguments
+    pub fn call(&mut self, name: &str, args: Vec<String>) -> Result<(), String> {
+        let engine = &self.engine;
+        let args: Vec<Dynamic> = args.into_iter().map(Dynamic::from).collect();
+        match self
+            .scripts
+            .iter_mut()
+            .find(|s| s.defines(name, args.len()))
+        {
+            Some(script) => script.call(engine, name, args),
+            None => Err(format!(
+                "no function {} taking {} arguments",
+                name,
+                args.len()
+            )),
+        }
+    }
+
+    /// Calls `on_line` in every script defining it, returns the errors
+    pub fn on_line(&mut self, line: &str) -> Vec<String> {
+        let engine = &self.engine;
+        self.scripts
+            .iter_mut()
+            .filter(|s| s.defines(ON_LINE, 1))
+            .filter_map(|s| {
+                s.call(engine, ON_LINE, vec![Dynamic::from(String::from(line))])
+                    .err()
+            })
+            .collect()
+    }
+
+    /// The actions of the scripts since the last call
+    pub fn take_actions(&self) -> Vec<ScriptAction> {
+        self.actions.borrow_mut().drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::Session;
+
+    fn scripts(source: &str) -> (Scripts, SharedSession, Result<(), String>) {
+        let session = Rc::new(RefCell::new(Session::default()));
+        let mut scripts = Scripts::new(PathBuf::from("scripts"), &session);
+        let loaded = scripts.load(PathBuf::from("test.rhai"), None, source);
+        (scripts, session, loaded)
+    }
+
+    #[test]
+    fn runs_the_hooks_of_a_script() {
+        let source = r#"
+            alias("h", "heal %1");
+            timer("tick", 60, "score");
+            fn on_line(line) {
+                if line.contains("is hungry") { send("eat bread"); }
+            }
+            fn target(name) { set_var("target", name); print("target " + name); }
+            fn lag() { set_var("lag", latency().to_string()); }
+        "#;
+        let (mut scripts, session, loaded) = scripts(source);
+        assert_eq!(loaded, Ok(()));
+
+        assert!(scripts.on_line("You are hungry.").is_empty());
+        assert!(scripts.on_line("Bob is hungry.").is_empty());
+        assert_eq!(scripts.call("target", vec![String::from("goblin")]), Ok(()));
+        assert!(scripts.call("target", Vec::new()).is_err());
+        assert_eq!(session.borrow().variables["target"], "goblin");
+        session.borrow_mut().latency = Some(Duration::from_millis(42));
+        assert_eq!(scripts.call("lag", Vec::new()), Ok(()));
+        assert_eq!(session.borrow().variables["lag"], "42");
+
+        let alias = ScriptAction::Alias {
+            pattern: String::from("h"),
+            body: String::from("heal %1"),
+        };
+        let timer = ScriptAction::Timer {
+            name: String::from("tick"),
+            seconds: 60.0,
+            command: String::from("score"),
+        };
+        assert_eq!(
+            scripts.take_actions(),
+            vec![
+                alias,
+                timer,
+                ScriptAction::Send(String::from("eat bread")),
+                ScriptAction::Echo(String::from("target goblin")),
+            ]
+        );
+    }
+
+    #[test]
+    fn rebuilds_the_definitions_on_reload() {
+        let dir = crate::test_dir("scripts");
+        let path = dir.join("triggers.rhai");
+        fs::write(&path, r#"trigger("hungry", "eat bread");"#).unwrap();
+        let session = Rc::new(RefCell::new(Session::default()));
+        let mut scripts = Scripts::new(dir.clone(), &session);
+        let start = Instant::now();
+
+        assert!(scripts.reload(start, false).is_empty());
+        let trigger = ScriptAction::Trigger {
+            pattern: String::from("hungry"),
+            action: String::from("eat bread"),
+        };
+        assert_eq!(scripts.take_actions(), vec![ScriptAction::Reset, trigger]);
+        assert!(scripts.reload(start + Duration::from_secs(2), false).is_empty());
+        assert!(scripts.take_actions().is_empty());
+
+        fs::remove_file(&path).unwrap();
+        assert!(scripts.reload(start + Duration::from_secs(4), false).is_empty());
+        assert_eq!(scripts.take_actions(), vec![ScriptAction::Reset]);
+        assert_eq!(scripts.iter().count(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reports_the_errors() {
+        let (_, _, loaded) = scripts("fn broken( {");
+        assert!(loaded.is_err());
+
+        let (endless, _, loaded) = scripts(r#"timer("t", 1e300, "score");"#);
+        assert!(loaded.is_err());
+        assert!(endless.take_actions().is_empty());
+
+        let (_, _, loaded) = scripts(r#"let s = "x"; for i in 0..27 { s += s; }"#);
+        assert!(loaded.is_err());
+        let (_, _, loaded) = scripts("let a = []; a.pad(1_000_000, 0);");
+        assert!(loaded.is_err());
+
+        let (mut scripts, _, loaded) = scripts("fn on_line(line) { loop { } }");
+        assert_eq!(loaded, Ok(()));
+        assert_eq!(scripts.on_line("Bob is hungry.").len(), 1);
+    }
+}
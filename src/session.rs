@@ -0,0 +1,24 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+use std::time::Duration;
+
+use mudnet::msdp::MsdpVal;
+
+/// What the server reported out of band, and the variables set while playing
+#[derive(Debug, Default)]
+pub struct Session {
+    /// last value of each MSDP variable
+    pub msdp: HashMap<String, MsdpVal>,
+    /// last data of each GMCP package, as json
+    pub gmcp: HashMap<String, String>,
+    /// last value of each ATCP message, keyed `Module.Message`
+    pub atcp: HashMap<String, String>,
+    /// round trip time to the server, measured by the keepalives
+    pub latency: Option<Duration>,
+    /// set with `:set`, or by the scripts
+    pub variables: BTreeMap<String, String>,
+}
+
+/// The session, shared with the scripts
+pub type SharedSession = Rc<RefCell<Session>>;
@@ -0,0 +1,251 @@
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+
+use log::{debug, warn};
+use mudnet::msp::{Sound, SoundKind};
+
+use crate::config::SoundConfig;
+
+/// What to do with the MSP triggers sent by the server
+pub trait SoundHandler {
+    fn handle(&mut self, sound: &Sound) -> io::Result<()>;
+}
+
+/// Looks the sounds up in a local media directory and plays them by running an external command
+pub struct MediaPlayer {
+    media_dir: PathBuf,
+    command: Vec<String>,
+    sound: Option<(u8, Child)>,
+    music: Option<(PathBuf, Child)>,
+}
+
+fn default_extension(kind: SoundKind) -> &'static str {
+    match kind {
+        SoundKind::Sound => "wav",
+        SoundKind::Music => "mid",
+    }
+}
+
+/// `*` matches any sequence of characters, `?` exactly one
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    fn matches(pattern: &[char], name: &[char]) -> bool {
+        match pattern.split_first() {
+            None => name.is_empty(),
+            Some(('*', rest)) => (0..=name.len()).any(|i| matches(rest, &name[i..])),
+            Some(('?', rest)) => !name.is_empty() && matches(rest, &name[1..]),
+            Some((c, rest)) => name.first() == Some(c) && matches(rest, &name[1..]),
+        }
+    }
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    matches(&pattern, &name)
+}
+
+fn find_file(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+
+    if !name.contains(|c| c == '*' || c == '?') {
+        return if path.is_file() {
+            Some(path.to_path_buf())
+        } else {
+            None
+        };
+    }
+
+    let mut found: Vec<PathBuf> = fs::read_dir(path.parent()?)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| {
+            p.is_file()
+                && p.file_name()
+                    .and_then(|n| n.to_str())
+                    .map_or(false, |n| wildcard_match(name, n))
+        })
+        .collect();
+    found.sort();
+    found.into_iter().next()
+}
+
+fn is_running(child: &mut Child) -> bool {
+    match child.try_wait() {
+        Ok(None) => true,
+        _ => false,
+    }
+}
+
+/// Kills the command if still running, and waits for it not to leave a zombie process.
+/// A command which exited was waited for by `is_running`.
+fn stop(child: &mut Child) {
+    if is_running(child) {
+        if let Err(e) = child.kill().and_then(|_| child.wait()) {
+            warn!("failed to stop sound command : {}", e);
+        }
+    }
+}
+
+impl MediaPlayer {
+    pub fn new(config: &SoundConfig) -> MediaPlayer {
+        MediaPlayer {
+            media_dir: config.media_dir.clone(),
+            command: config.command.clone(),
+            sound: None,
+            music: None,
+        }
+    }
+
+    /// Resolves the file of a trigger inside the media directory,
+    /// first in the sub directory named after its type, then at the root.
+    pub fn lookup(&self, sound: &Sound) -> Option<PathBuf> {
+        let file = Path::new(&sound.file);
+
+        // the name comes from the server, it must not escape the media directory
+        if !file.components().all(|c| match c {
+            Component::Normal(_) => true,
+            _ => false,
+        }) {
+            warn!("rejecting MSP file {:?}", sound.file);
+            return None;
+        }
+
+        let file = if file.extension().is_some() {
+            file.to_path_buf()
+        } else {
+            file.with_extension(default_extension(sound.kind))
+        };
+
+        let typed = sound.sound_type.as_ref().map(|t| self.media_dir.join(t));
+
+        typed
+            .iter()
+            .chain(std::iter::once(&self.media_dir))
+            .filter_map(|dir| find_file(&dir.join(&file)))
+            .next()
+    }
+
+    fn spawn(&self, path: &Path, sound: &Sound) -> io::Result<Child> {
+        let args: Vec<String> = self
+            .command
+            .iter()
+            .map(|arg| {
+                arg.replace("{file}", &path.to_string_lossy())
+                    .replace("{volume}", &sound.volume.to_string())
+                    .replace("{loops}", &sound.loops.to_string())
+                    .replace("{priority}", &sound.priority.to_string())
+                    .replace("{type}", sound.sound_type.as_ref().map_or("", |t| t.as_str()))
+                    .replace("{url}", sound.url.as_ref().map_or("", |u| u.as_str()))
+            })
+            .collect();
+
+        debug!("playing {:?} with {:?}", path, args);
+
+        Command::new(&args[0])
+            .args(&args[1..])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+    }
+
+    fn stop(&mut self, kind: SoundKind) {
+        match kind {
+            SoundKind::Sound => self.sound.take().map(|(_, mut child)| stop(&mut child)),
+            SoundKind::Music => self.music.take().map(|(_, mut child)| stop(&mut child)),
+        };
+    }
+}
+
+impl SoundHandler for MediaPlayer {
+    fn handle(&mut self, sound: &Sound) -> io::Result<()> {
+        if sound.is_off() {
+            self.stop(sound.kind);
+            return Ok(());
+        }
+
+        let path = match self.lookup(sound) {
+            Some(path) => path,
+            None => {
+                debug!("no local media for {:?}", sound);
+                return Ok(());
+            }
+        };
+
+        if self.command.is_empty() {
+            debug!("no sound command configured, ignoring {:?}", path);
+            return Ok(());
+        }
+
+        match sound.kind {
+            SoundKind::Sound => {
+                if let Some((priority, child)) = self.sound.as_mut() {
+                    if *priority > sound.priority && is_running(child) {
+                        return Ok(());
+                    }
+                }
+                self.stop(SoundKind::Sound);
+                let child = self.spawn(&path, sound)?;
+                self.sound = Some((sound.priority, child));
+            }
+            SoundKind::Music => {
+                if let Some((playing, child)) = self.music.as_mut() {
+                    if sound.continues && *playing == path && is_running(child) {
+                        return Ok(());
+                    }
+                }
+                self.stop(SoundKind::Music);
+                let child = self.spawn(&path, sound)?;
+                self.music = Some((path, child));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards() {
+        assert!(wildcard_match("hit*.wav", "hit2.wav"));
+        assert!(wildcard_match("hit?.wav", "hit2.wav"));
+        assert!(!wildcard_match("hit?.wav", "hit.wav"));
+        assert!(wildcard_match("*", "anything"));
+    }
+
+    #[test]
+    fn lookup_in_media_dir() -> io::Result<()> {
+        let media_dir = crate::test_dir("sound-lookup");
+        fs::create_dir_all(media_dir.join("combat"))?;
+        fs::write(media_dir.join("combat").join("hit1.wav"), b"")?;
+        fs::write(media_dir.join("door.wav"), b"")?;
+
+        let player = MediaPlayer::new(&SoundConfig {
+            media_dir: media_dir.clone(),
+            command: Vec::new(),
+        });
+
+        let mut sound = Sound {
+            kind: SoundKind::Sound,
+            file: String::from("hit*"),
+            volume: 100,
+            loops: 1,
+            priority: 50,
+            continues: true,
+            sound_type: Some(String::from("combat")),
+            url: None,
+        };
+        assert_eq!(
+            player.lookup(&sound),
+            Some(media_dir.join("combat").join("hit1.wav"))
+        );
+
+        sound.file = String::from("door");
+        assert_eq!(player.lookup(&sound), Some(media_dir.join("door.wav")));
+
+        sound.file = String::from("../door.wav");
+        assert_eq!(player.lookup(&sound), None);
+        fs::remove_dir_all(&media_dir)
+    }
+}
@@ -0,0 +1,182 @@
+use std::cmp::Reverse;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use log::warn;
+use regex::Regex;
+
+use crate::config::SpeedwalkConfig;
+use crate::timer;
+use crate::trigger::regex_source;
+
+const WALK_COMMAND: &'static str = "#walk";
+
+/// Expands the speedwalks into their steps, sent one after the other when they are delayed
+pub struct Speedwalk {
+    /// the directions and the commands they send, the longest directions first
+    directions: Vec<(String, String)>,
+    delay: Duration,
+    failure: Option<Regex>,
+    max_steps: usize,
+    /// steps of the current walk not sent yet
+    steps: VecDeque<String>,
+    next: Instant,
+}
+
+impl Speedwalk {
+    pub fn new(config: &SpeedwalkConfig, max_steps: usize) -> Speedwalk {
+        let mut directions: Vec<(String, String)> = config
+            .directions
+            .iter()
+            .filter(|(direction, _)| !direction.is_empty())
+            .map(|(direction, command)| (direction.clone(), command.clone()))
+            .collect();
+        directions.sort_by_key(|(direction, _)| Reverse(direction.len()));
+
+        let failure = match config.failure.as_str() {
+            "" => None,
+            failure => match Regex::new(&regex_source(failure)) {
+                Ok(regex) => Some(regex),
+                Err(e) => {
+                    warn!("invalid speedwalk failure {} : {}", failure, e);
+                    None
+                }
+            },
+        };
+        let delay = timer::check_delay(config.delay).unwrap_or_else(|e| {
+            warn!("speedwalk delay ignored : {}", e);
+            0.0
+        });
+        Speedwalk {
+            directions,
+            delay: Duration::from_secs_f64(delay),
+            failure,
+            max_steps,
+            steps: VecDeque::new(),
+            next: Instant::now(),
+        }
+    }
+
+    /// The commands of a word made of directions with counts, e.g. `3n2e4s`,
+    /// the counts being optional unless required
+    fn parse(&self, walk: &str, counts: bool, steps: &mut Vec<String>) -> Option<()> {
+        let mut rest = walk;
+        while !rest.is_empty() {
+            let len = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+            let count = match len {
+                0 if counts => return None,
+                0 => 1,
+                _ => rest[..len].parse().ok()?,
+            };
+            rest = &rest[len..];
+
+            let (direction, command) = self
+                .directions
+                .iter()
+                .find(|(direction, _)| rest.starts_with(direction.as_str()))?;
+            rest = &rest[direction.len()..];
+            let count = count.min(self.max_steps.saturating_sub(steps.len()));
+            for _ in 0..count {
+                steps.push(command.clone());
+            }
+        }
+        Some(())
+    }
+
+    /// The steps of `#walk 3n e`, or of a single word such as `3n2e4s`.
+    /// Each direction of the word needs a count, not to take a command such as `news`
+    /// or `2nd` for a walk.
+    pub fn expand(&self, command: &str) -> Option<Vec<String>> {
+        let command = command.trim();
+        let mut steps = Vec::new();
+        if command == WALK_COMMAND || command.starts_with("#walk ") {
+            for word in command[WALK_COMMAND.len()..].split_whitespace() {
+                self.parse(word, false, &mut steps)?;
+            }
+        } else if command.contains(|c: char| c.is_ascii_digit())
+            && !command.contains(char::is_whitespace)
+        {
+            self.parse(command, true, &mut steps)?;
+        } else {
+            return None;
+        }
+        Some(steps)
+    }
+
+    /// Starts a walk in place of the current one, `#walk` alone stopping it.
+    /// Returns the steps to send now: all of them without a delay, else the first one.
+    pub fn start(&mut self, steps: Vec<String>, now: Instant) -> Vec<String> {
+        self.steps = steps.into();
+        if self.delay == Duration::from_secs(0) {
+            return self.steps.drain(..).collect();
+        }
+        self.next = now + self.delay;
+        self.steps.pop_front().into_iter().collect()
+    }
+
+    /// The next step of the walk, once it is due
+    pub fn run(&mut self, now: Instant) -> Option<String> {
+        if self.steps.is_empty() || now < self.next {
+            return None;
+        }
+        self.next = now + self.delay;
+        self.steps.pop_front()
+    }
+
+    /// Stops the walk when the line tells a step failed, returns the number of steps dropped
+    pub fn check_failure(&mut self, line: &str) -> usize {
+        match &self.failure {
+            Some(failure) if !self.steps.is_empty() && failure.is_match(line) => {
+                let dropped = self.steps.len();
+                self.steps.clear();
+                dropped
+            }
+            _ => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn speedwalk(delay: f64) -> Speedwalk {
+        let mut config = SpeedwalkConfig::default();
+        config.directions.insert(String::from("u"), String::from("up"));
+        config.delay = delay;
+        Speedwalk::new(&config, 100)
+    }
+
+    #[test]
+    fn expands_the_walks() {
+        let speedwalk = speedwalk(0.0);
+        assert_eq!(
+            speedwalk.expand("2n1ne2u").unwrap(),
+            vec!["n", "n", "ne", "up", "up"]
+        );
+        assert_eq!(speedwalk.expand("#walk 2e in s").unwrap(), vec!["e", "e", "in", "s"]);
+        assert_eq!(speedwalk.expand("#walk").unwrap(), Vec::<String>::new());
+        assert_eq!(speedwalk.expand("news"), None);
+        assert_eq!(speedwalk.expand("2nd"), None);
+        assert_eq!(speedwalk.expand("#walk 2n d").unwrap(), vec!["n", "n", "d"]);
+        assert_eq!(speedwalk.expand("3"), None);
+        assert_eq!(speedwalk.expand("12north"), None);
+        assert_eq!(speedwalk.expand("#walk 2x"), None);
+        assert_eq!(speedwalk.expand("500n").unwrap().len(), 100);
+    }
+
+    #[test]
+    fn delays_the_steps_until_a_failure() {
+        let start = Instant::now();
+        let at = |seconds: u64| start + Duration::from_secs(seconds);
+        let mut speedwalk = speedwalk(1.0);
+
+        let steps = speedwalk.expand("3n2e").unwrap();
+        assert_eq!(speedwalk.start(steps, start), vec!["n"]);
+        assert_eq!(speedwalk.run(start), None);
+        assert_eq!(speedwalk.run(at(1)), Some(String::from("n")));
+        assert_eq!(speedwalk.check_failure("A dark room."), 0);
+        assert_eq!(speedwalk.check_failure("You can't go that way."), 3);
+        assert_eq!(speedwalk.run(at(5)), None);
+    }
+}
@@ -0,0 +1,309 @@
+use std::time::{Duration, Instant};
+
+use log::warn;
+
+use crate::world::TimerConfig;
+
+/// Longest period or delay of a timer, a week
+pub const MAX_SECONDS: f64 = 7.0 * 86_400.0;
+
+/// Commands run after a delay, once or periodically
+pub struct Timer {
+    pub config: TimerConfig,
+    /// `None` for a one-shot delayed command
+    period: Option<Duration>,
+    next: Instant,
+    /// time left before the next run while paused
+    paused: Option<Duration>,
+    /// the warning was run for the next run
+    warned: bool,
+}
+
+fn duration(seconds: f64) -> Duration {
+    // checked when the timers are defined, still kept in range not to panic, NaN included
+    if seconds > 0.0 {
+        Duration::from_secs_f64(seconds.min(MAX_SECONDS))
+    } else {
+        Duration::from_secs(0)
+    }
+}
+
+/// Checks the period of a timer: above 0, not to run on every tick, and at most `MAX_SECONDS`
+pub fn check_period(seconds: f64) -> Result<f64, String> {
+    if seconds > 0.0 && seconds <= MAX_SECONDS {
+        Ok(seconds)
+    } else {
+        Err(format!("invalid period {}, from 0 to {} seconds", seconds, MAX_SECONDS))
+    }
+}
+
+/// Checks a delay, or the time a warning comes before a run: from 0 to `MAX_SECONDS`
+pub fn check_delay(seconds: f64) -> Result<f64, String> {
+    if (0.0..=MAX_SECONDS).contains(&seconds) {
+        Ok(seconds)
+    } else {
+        Err(format!("invalid delay {}, from 0 to {} seconds", seconds, MAX_SECONDS))
+    }
+}
+
+impl Timer {
+    fn new(config: TimerConfig, period: Option<Duration>, delay: Duration, now: Instant) -> Timer {
+        Timer {
+            config,
+            period,
+            next: now + delay,
+            paused: None,
+            warned: false,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.is_some()
+    }
+
+    pub fn is_periodic(&self) -> bool {
+        self.period.is_some()
+    }
+
+    /// Time left before the next run
+    pub fn remaining(&self, now: Instant) -> Duration {
+        self.paused.unwrap_or_else(|| self.next.saturating_duration_since(now))
+    }
+
+    /// Commands due at the given time, the warning first.
+    /// Returns whether the timer runs again.
+    fn run(&mut self, now: Instant, commands: &mut Vec<String>) -> bool {
+        if self.is_paused() {
+            return true;
+        }
+        let warning = duration(self.config.warn);
+        if !self.warned && self.config.warn > 0.0 && now + warning >= self.next {
+            self.warned = true;
+            if !self.config.warn_command.is_empty() {
+                commands.push(self.config.warn_command.clone());
+            }
+        }
+        if now < self.next {
+            return true;
+        }
+
+        commands.push(self.config.command.clone());
+        match self.period {
+            Some(period) => {
+                self.next += period;
+                // the missed runs are skipped, e.g. after a suspend
+                if self.next <= now {
+                    self.next = now + period;
+                }
+                self.warned = false;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// The timers of the session, run on each tick of the UI
+#[derive(Default)]
+pub struct Timers {
+    timers: Vec<Timer>,
+    /// number naming the next delayed command
+    delays: usize,
+}
+
+impl Timers {
+    pub fn from_config(configs: &[TimerConfig], now: Instant) -> Timers {
+        let mut timers = Timers::default();
+        for config in configs {
+            match check_period(config.seconds) {
+                Ok(_) => timers.add(config.clone(), now),
+                Err(e) => warn!("timer {} ignored : {}", config.name, e),
+            }
+        }
+        timers
+    }
+
+    /// The periodic timers, the delayed commands and the timers of the scripts not being saved
+    pub fn to_config(&self) -> Vec<TimerConfig> {
+        self.timers
+            .iter()
+            .filter(|t| t.is_periodic() && !t.config.scripted)
+            .map(|t| t.config.clone())
+            .collect()
+    }
+
+    /// Adds a periodic timer, replacing the one with the same name
+    pub fn add(&mut self, config: TimerConfig, now: Instant) {
+        let period = duration(config.seconds);
+        let timer = Timer::new(config, Some(period), period, now);
+        match self.timers.iter_mut().find(|t| t.config.name == timer.config.name) {
+            Some(existing) => *existing = timer,
+            None => self.timers.push(timer),
+        }
+    }
+
+    /// Runs a command once after some seconds, returns the name of its timer
+    pub fn delay(&mut self, seconds: f64, command: &str, now: Instant) -> String {
+        self.delays += 1;
+        let config = TimerConfig {
+            name: format!("delay-{}", self.delays),
+            seconds,
+            command: String::from(command),
+            ..TimerConfig::default()
+        };
+        let name = config.name.clone();
+        self.timers.push(Timer::new(config, None, duration(seconds), now));
+        name
+    }
+
+    /// Runs a command some seconds before each run of a periodic timer
+    pub fn set_warning(&mut self, name: &str, seconds: f64, command: &str) -> bool {
+        match self.find(name) {
+            Some(timer) if timer.is_periodic() => {
+                timer.config.warn = seconds;
+                timer.config.warn_command = String::from(command);
+                timer.warned = false;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn find(&mut self, name: &str) -> Option<&mut Timer> {
+        self.timers.iter_mut().find(|t| t.config.name == name)
+    }
+
+    pub fn pause(&mut self, name: &str, now: Instant) -> bool {
+        match self.find(name) {
+            Some(timer) => {
+                timer.paused = Some(timer.remaining(now));
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn resume(&mut self, name: &str, now: Instant) -> bool {
+        match self.find(name) {
+            Some(timer) => {
+                if let Some(remaining) = timer.paused.take() {
+                    timer.next = now + remaining;
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Restarts the period of a timer from now, e.g. when the tick message of the MUD is seen
+    pub fn resync(&mut self, name: &str, now: Instant) -> bool {
+        match self.find(name) {
+            Some(timer) => {
+                let period = timer.period.unwrap_or_else(|| duration(timer.config.seconds));
+                timer.next = now + period;
+                timer.paused = timer.paused.map(|_| period);
+                timer.warned = false;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        let len = self.timers.len();
+        self.timers.retain(|t| t.config.name != name);
+        self.timers.len() != len
+    }
+
+    pub fn remove_scripted(&mut self) {
+        self.timers.retain(|t| !t.config.scripted);
+    }
+
+    /// Whether the user defined a timer with the name, which the scripts do not replace
+    pub fn is_user_defined(&self, name: &str) -> bool {
+        self.timers.iter().any(|t| t.config.name == name && !t.config.scripted)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Timer> {
+        self.timers.iter()
+    }
+
+    /// Commands of the timers due at the given time, the one-shot timers being removed once run
+    pub fn run(&mut self, now: Instant) -> Vec<String> {
+        let mut commands = Vec::new();
+        let mut timers = Vec::new();
+        for mut timer in self.timers.drain(..) {
+            if timer.run(now, &mut commands) {
+                timers.push(timer);
+            }
+        }
+        self.timers = timers;
+        commands
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ticker() -> TimerConfig {
+        TimerConfig {
+            name: String::from("tick"),
+            seconds: 60.0,
+            command: String::from("score"),
+            warn: 5.0,
+            warn_command: String::from("say tick soon"),
+            scripted: false,
+        }
+    }
+
+    #[test]
+    fn runs_periodically_with_a_warning() {
+        let start = Instant::now();
+        let at = |seconds: u64| start + Duration::from_secs(seconds);
+        let mut timers = Timers::from_config(&[ticker()], start);
+
+        assert!(timers.run(at(10)).is_empty());
+        assert_eq!(timers.run(at(56)), vec!["say tick soon"]);
+        assert!(timers.run(at(57)).is_empty());
+        assert_eq!(timers.run(at(60)), vec!["score"]);
+        assert_eq!(timers.run(at(120)), vec!["say tick soon", "score"]);
+
+        assert!(timers.resync("tick", at(150)));
+        assert!(timers.run(at(180)).is_empty());
+        assert_eq!(timers.run(at(210)), vec!["say tick soon", "score"]);
+    }
+
+    #[test]
+    fn delays_and_pauses() {
+        let start = Instant::now();
+        let at = |seconds: u64| start + Duration::from_secs(seconds);
+        let mut timers = Timers::default();
+
+        let name = timers.delay(3.0, "stand", start);
+        assert!(timers.pause(&name, at(1)));
+        assert!(timers.run(at(10)).is_empty());
+        assert!(timers.resume(&name, at(10)));
+        assert_eq!(timers.iter().next().unwrap().remaining(at(10)), Duration::from_secs(2));
+        assert_eq!(timers.run(at(12)), vec!["stand"]);
+        assert_eq!(timers.iter().count(), 0);
+        assert!(timers.to_config().is_empty());
+    }
+
+    #[test]
+    fn checks_the_seconds() {
+        assert_eq!(check_period(2.5), Ok(2.5));
+        assert!(check_period(0.0).is_err());
+        assert!(check_period(f64::INFINITY).is_err());
+        assert!(check_period(f64::NAN).is_err());
+        assert_eq!(check_delay(0.0), Ok(0.0));
+        assert!(check_delay(1e30).is_err());
+
+        let spam = TimerConfig {
+            seconds: 0.0,
+            ..ticker()
+        };
+        assert_eq!(Timers::from_config(&[spam], Instant::now()).iter().count(), 0);
+        assert_eq!(duration(1e300), Duration::from_secs_f64(MAX_SECONDS));
+    }
+}
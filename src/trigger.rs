@@ -0,0 +1,385 @@
+pub mod multiline;
+
+use std::cmp::Reverse;
+use std::collections::HashSet;
+
+use log::warn;
+use regex::{Regex, RegexSet};
+
+use crate::alias;
+use crate::config::CommandConfig;
+use crate::world::{MultiTriggerConfig, TriggerConfig};
+use multiline::MultiTrigger;
+
+/// Commands run when a server line matches a pattern
+pub struct Trigger {
+    pub config: TriggerConfig,
+    regex: Regex,
+    /// number of times the trigger fired since the start
+    pub fires: usize,
+}
+
+/// The source of the regex of a pattern: a regex between slashes, or a text found anywhere in the line
+pub fn regex_source(pattern: &str) -> String {
+    if pattern.len() > 1 && pattern.starts_with('/') && pattern.ends_with('/') {
+        String::from(&pattern[1..pattern.len() - 1])
+    } else {
+        regex::escape(pattern)
+    }
+}
+
+impl Trigger {
+    pub fn new(config: TriggerConfig) -> Result<Trigger, regex::Error> {
+        let regex = Regex::new(&regex_source(&config.pattern))?;
+        Ok(Trigger {
+            config,
+            regex,
+            fires: 0,
+        })
+    }
+}
+
+/// Splits the pattern starting the text from what follows it,
+/// the pattern being a regex between slashes, a quoted text or a word
+pub fn next_pattern(text: &str) -> Result<(&str, &str), String> {
+    let text = text.trim_start();
+    let (pattern, rest) = if text.starts_with('"') {
+        match text[1..].find('"') {
+            Some(i) => (&text[1..i + 1], text[i + 2..].trim()),
+            None => return Err(String::from("unterminated quote")),
+        }
+    } else {
+        alias::split_definition(text)
+    };
+    if pattern.is_empty() {
+        return Err(String::from("no pattern"));
+    }
+    Ok((pattern, rest))
+}
+
+/// The pattern making the whole text: a regex between slashes, a quoted text,
+/// or the text itself, e.g. `Bob misses you`
+pub fn whole_pattern(text: &str) -> Result<&str, String> {
+    let text = text.trim();
+    if text.starts_with('"') || text.starts_with('/') {
+        let (pattern, rest) = next_pattern(text)?;
+        let is_regex = pattern.len() > 1 && pattern.starts_with('/') && pattern.ends_with('/');
+        if text.starts_with('"') || is_regex {
+            return match rest {
+                "" => Ok(pattern),
+                rest => Err(format!("unexpected {} after the pattern", rest)),
+            };
+        }
+    }
+    if text.is_empty() {
+        return Err(String::from("no pattern"));
+    }
+    Ok(text)
+}
+
+/// Parses `[-p <priority>] [-once] [-prompt] [-g <group>] <pattern> <action>`,
+/// the pattern being a regex between slashes, a quoted text or a word
+pub fn parse_definition(definition: &str) -> Result<TriggerConfig, String> {
+    let mut config = TriggerConfig::default();
+    let mut rest = definition.trim();
+
+    while rest.starts_with('-') {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let (flag, after) = (&rest[..end], rest[end..].trim_start());
+        rest = match flag {
+            "-once" => {
+                config.once = true;
+                after
+            }
+            "-prompt" => {
+                config.prompt = true;
+                after
+            }
+            "-p" | "-g" => {
+                let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                let value = &after[..end];
+                if flag == "-p" {
+                    config.priority = value
+                        .parse()
+                        .map_err(|_| format!("invalid priority {}", value))?;
+                } else {
+                    config.group = String::from(value);
+                }
+                after[end..].trim_start()
+            }
+            _ => return Err(format!("unknown option {}", flag)),
+        };
+    }
+
+    let (pattern, action) = next_pattern(rest)?;
+    config.pattern = String::from(pattern);
+    config.action = String::from(action.trim());
+    Ok(config)
+}
+
+/// The triggers of the world, all matched at once by a set of regexes
+pub struct Triggers {
+    triggers: Vec<Trigger>,
+    set: RegexSet,
+    multi: Vec<MultiTrigger>,
+    disabled_groups: HashSet<String>,
+}
+
+impl Default for Triggers {
+    fn default() -> Triggers {
+        Triggers {
+            triggers: Vec::new(),
+            set: RegexSet::empty(),
+            multi: Vec::new(),
+            disabled_groups: HashSet::new(),
+        }
+    }
+}
+
+impl Triggers {
+    /// Compiles the saved triggers, the invalid ones being skipped
+    pub fn from_config(
+        configs: &[TriggerConfig],
+        multi_configs: &[MultiTriggerConfig],
+        disabled_groups: &[String],
+    ) -> Triggers {
+        let mut triggers = Triggers::default();
+        for config in configs {
+            match Trigger::new(config.clone()) {
+                Ok(trigger) => triggers.triggers.push(trigger),
+                Err(e) => warn!("invalid trigger {} : {}", config.pattern, e),
+            }
+        }
+        for config in multi_configs {
+            match MultiTrigger::new(config.clone()) {
+                Ok(trigger) => triggers.multi.push(trigger),
+                Err(e) => warn!("invalid multi-line trigger {:?} : {}", config.patterns, e),
+            }
+        }
+        triggers.disabled_groups = disabled_groups.iter().cloned().collect();
+        triggers.compile();
+        triggers
+    }
+
+    /// The triggers to save, the ones of the scripts being left out
+    pub fn to_config(&self) -> Vec<TriggerConfig> {
+        self.triggers
+            .iter()
+            .filter(|t| !t.config.scripted)
+            .map(|t| t.config.clone())
+            .collect()
+    }
+
+    pub fn to_multi_config(&self) -> Vec<MultiTriggerConfig> {
+        self.multi.iter().map(|t| t.config.clone()).collect()
+    }
+
+    pub fn disabled_groups(&self) -> Vec<String> {
+        let mut groups: Vec<String> = self.disabled_groups.iter().cloned().collect();
+        groups.sort();
+        groups
+    }
+
+    fn compile(&mut self) {
+        let sources = self.triggers.iter().map(|t| t.regex.as_str());
+        // the regexes were all compiled already
+        self.set = RegexSet::new(sources).unwrap_or_else(|_| RegexSet::empty());
+    }
+
+    /// Adds a trigger, replacing the one with the same pattern
+    pub fn set(&mut self, trigger: Trigger) {
+        let pattern = &trigger.config.pattern;
+        match self.triggers.iter_mut().find(|t| &t.config.pattern == pattern) {
+            Some(existing) => *existing = trigger,
+            None => self.triggers.push(trigger),
+        }
+        self.compile();
+    }
+
+    /// Adds a multi-line trigger, replacing the one with the same first pattern
+    pub fn set_multi(&mut self, trigger: MultiTrigger) {
+        match self.multi.iter_mut().find(|t| t.pattern() == trigger.pattern()) {
+            Some(existing) => *existing = trigger,
+            None => self.multi.push(trigger),
+        }
+    }
+
+    /// Removes the trigger with the given pattern, or the multi-line one with the given first pattern
+    pub fn remove(&mut self, pattern: &str) -> bool {
+        let len = self.triggers.len() + self.multi.len();
+        self.triggers.retain(|t| t.config.pattern != pattern);
+        self.multi.retain(|t| t.pattern() != pattern);
+        self.compile();
+        self.triggers.len() + self.multi.len() != len
+    }
+
+    pub fn remove_scripted(&mut self) {
+        self.triggers.retain(|t| !t.config.scripted);
+        self.compile();
+    }
+
+    /// Whether the user defined a trigger with the pattern, which the scripts do not replace
+    pub fn is_user_defined(&self, pattern: &str) -> bool {
+        self.triggers
+            .iter()
+            .any(|t| t.config.pattern == pattern && !t.config.scripted)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Trigger> {
+        self.triggers.iter()
+    }
+
+    pub fn iter_multi(&self) -> impl Iterator<Item = &MultiTrigger> {
+        self.multi.iter()
+    }
+
+    pub fn set_group_enabled(&mut self, group: &str, enabled: bool) {
+        if enabled {
+            self.disabled_groups.remove(group);
+        } else {
+            self.disabled_groups.insert(String::from(group));
+        }
+    }
+
+    pub fn is_enabled(&self, trigger: &Trigger) -> bool {
+        self.is_group_enabled(&trigger.config.group)
+    }
+
+    pub fn is_group_enabled(&self, group: &str) -> bool {
+        !self.disabled_groups.contains(group)
+    }
+
+    /// Actions of the enabled triggers matching a line, by decreasing priority, then of the completed multi-line ones.
+    /// Only the triggers allowing it are matched against a prompt, the one-shot triggers are removed once fired.
+    /// The text of the line substituted in the actions is escaped, so that it cannot run other commands.
+    pub fn fire(&mut self, line: &str, prompt: bool, config: &CommandConfig) -> Vec<String> {
+        let mut matched: Vec<usize> = self
+            .set
+            .matches(line)
+            .into_iter()
+            .filter(|i| {
+                let trigger = &self.triggers[*i];
+                self.is_enabled(trigger) && (!prompt || trigger.config.prompt)
+            })
+            .collect();
+        // the sort is stable, triggers of the same priority fire in the order they were defined
+        matched.sort_by_key(|i| Reverse(self.triggers[*i].config.priority));
+
+        let mut actions = Vec::new();
+        for i in matched.iter() {
+            let trigger = &mut self.triggers[*i];
+            trigger.fires += 1;
+            if let Some(captures) = trigger.regex.captures(line) {
+                let action = alias::substitute_action(&trigger.config.action, captures, config);
+                if !action.is_empty() {
+                    actions.push(action);
+                }
+            }
+        }
+
+        let triggers = &self.triggers;
+        if matched.iter().any(|i| triggers[*i].config.once) {
+            let mut index = 0;
+            self.triggers.retain(|t| {
+                let fired_once = t.config.once && matched.contains(&index);
+                index += 1;
+                !fired_once
+            });
+            self.compile();
+        }
+
+        if !prompt {
+            let disabled_groups = &self.disabled_groups;
+            for trigger in self.multi.iter_mut() {
+                if !disabled_groups.contains(&trigger.config.group) {
+                    actions.extend(trigger.feed(line, config).filter(|action| !action.is_empty()));
+                }
+            }
+        }
+        actions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command;
+
+    fn triggers(definitions: &[&str]) -> Triggers {
+        let mut triggers = Triggers::default();
+        for definition in definitions {
+            triggers.set(Trigger::new(parse_definition(definition).unwrap()).unwrap());
+        }
+        triggers
+    }
+
+    #[test]
+    fn parses_definitions() {
+        let config = parse_definition("-p 5 -once -g combat \"You are hungry\" eat bread").unwrap();
+        assert_eq!(config.priority, 5);
+        assert!(config.once);
+        assert_eq!(config.group, "combat");
+        assert_eq!(config.pattern, "You are hungry");
+        assert_eq!(config.action, "eat bread");
+
+        let config = parse_definition("/^(\\w+) arrives/ say hi %1").unwrap();
+        assert_eq!(config.pattern, "/^(\\w+) arrives/");
+        assert!(parse_definition("-x hungry eat").is_err());
+    }
+
+    #[test]
+    fn takes_the_whole_pattern() {
+        assert_eq!(whole_pattern(" Bob misses you "), Ok("Bob misses you"));
+        assert_eq!(whole_pattern("\"You are hungry\""), Ok("You are hungry"));
+        assert_eq!(whole_pattern("/^(\\w+) arrives/"), Ok("/^(\\w+) arrives/"));
+        assert_eq!(whole_pattern("/me waves"), Ok("/me waves"));
+        assert!(whole_pattern("\"Bob\" misses").is_err());
+        assert!(whole_pattern("/Bob/ misses").is_err());
+        assert!(whole_pattern("").is_err());
+    }
+
+    #[test]
+    fn fires_by_priority() {
+        let mut triggers = triggers(&[
+            "/^(\\w+) arrives/ say hi %1",
+            "-p 2 -g combat arrives wield sword",
+            "-once -p 1 Bob bow bob",
+        ]);
+        let config = CommandConfig::default();
+
+        assert_eq!(
+            triggers.fire("Bob arrives from the north.", false, &config),
+            vec!["wield sword", "bow bob", "say hi Bob"]
+        );
+        assert_eq!(triggers.iter().count(), 2);
+        assert_eq!(triggers.iter().next().unwrap().fires, 1);
+
+        triggers.set_group_enabled("combat", false);
+        assert_eq!(triggers.fire("Bob arrives", false, &config), vec!["say hi Bob"]);
+        assert!(triggers.fire("Bob arrives", true, &config).is_empty());
+    }
+
+    #[test]
+    fn sorts_the_extreme_priorities() {
+        let mut triggers = triggers(&["-p -2147483648 arrives bow", "-p 2147483647 Bob wave"]);
+        let config = CommandConfig::default();
+        assert_eq!(triggers.fire("Bob arrives", false, &config), vec!["wave", "bow"]);
+    }
+
+    #[test]
+    fn escapes_the_server_text() {
+        let mut triggers = triggers(&["/^(.+) arrives/ say hi %1", "/^(.+) leaves/ %1"]);
+        let config = CommandConfig::default();
+
+        let actions = triggers.fire("Bob;:alias n give all to bob arrives", false, &config);
+        assert_eq!(actions, vec!["say hi Bob\\;:alias n give all to bob"]);
+        assert_eq!(
+            command::split(&actions[0], &config.separator),
+            vec!["say hi Bob;:alias n give all to bob"]
+        );
+        assert_eq!(
+            triggers.fire(":set x 1 leaves", false, &config),
+            vec!["::set x 1"]
+        );
+    }
+}
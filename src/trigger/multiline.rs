@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+
+use regex::{Captures, Regex};
+
+use super::{next_pattern, regex_source};
+use crate::alias;
+use crate::config::CommandConfig;
+use crate::world::MultiTriggerConfig;
+
+/// A match in progress, waiting for its following lines
+struct Pending {
+    /// index of the next pattern of the sequence
+    next: usize,
+    /// lines received since the first one, included
+    lines: usize,
+    matched: Vec<String>,
+    groups: Vec<String>,
+    names: HashMap<String, String>,
+}
+
+impl Pending {
+    fn collect(&mut self, regex: &Regex, captures: &Captures) {
+        self.matched.push(String::from(&captures[0]));
+        for i in 1..captures.len() {
+            self.groups
+                .push(captures.get(i).map_or(String::new(), |m| String::from(m.as_str())));
+        }
+        for name in regex.capture_names().flatten() {
+            if let Some(m) = captures.name(name) {
+                self.names.insert(String::from(name), String::from(m.as_str()));
+            }
+        }
+    }
+}
+
+/// A trigger matching a sequence of lines, firing once all of them were received
+pub struct MultiTrigger {
+    pub config: MultiTriggerConfig,
+    regexes: Vec<Regex>,
+    until: Option<Regex>,
+    pending: Option<Pending>,
+    /// number of times the trigger fired since the start
+    pub fires: usize,
+}
+
+impl MultiTrigger {
+    pub fn new(config: MultiTriggerConfig) -> Result<MultiTrigger, regex::Error> {
+        let mut regexes = Vec::new();
+        for pattern in config.patterns.iter() {
+            regexes.push(Regex::new(&regex_source(pattern))?);
+        }
+        let until = match config.until.as_str() {
+            "" => None,
+            until => Some(Regex::new(&regex_source(until))?),
+        };
+        Ok(MultiTrigger {
+            config,
+            regexes,
+            until,
+            pending: None,
+            fires: 0,
+        })
+    }
+
+    /// The first pattern, naming the trigger
+    pub fn pattern(&self) -> &str {
+        self.config.patterns.first().map_or("", String::as_str)
+    }
+
+    /// Matches a line against the pattern awaited, giving the action once the match is complete.
+    /// With a closing pattern, the lines up to the closing one are matched against any of the patterns.
+    pub fn feed(&mut self, line: &str, config: &CommandConfig) -> Option<String> {
+        let mut consumed = false;
+        let mut complete = false;
+
+        if let Some(pending) = self.pending.as_mut() {
+            pending.lines += 1;
+            let window = self.config.window();
+            if window > 0 && pending.lines > window {
+                self.pending = None;
+            } else if let Some(until) = &self.until {
+                if let Some(captures) = until.captures(line) {
+                    pending.collect(until, &captures);
+                    consumed = true;
+                    complete = true;
+                } else if let Some((regex, captures)) = self.regexes[1..]
+                    .iter()
+                    .find_map(|regex| regex.captures(line).map(|c| (regex, c)))
+                {
+                    pending.collect(regex, &captures);
+                    consumed = true;
+                }
+            } else if let Some(captures) = self.regexes[pending.next].captures(line) {
+                pending.collect(&self.regexes[pending.next], &captures);
+                pending.next += 1;
+                consumed = true;
+                complete = pending.next == self.regexes.len();
+            }
+        }
+
+        if !consumed {
+            if let Some(captures) = self.regexes.first().and_then(|r| r.captures(line)) {
+                let mut pending = Pending {
+                    next: 1,
+                    lines: 1,
+                    matched: Vec::new(),
+                    groups: Vec::new(),
+                    names: HashMap::new(),
+                };
+                pending.collect(&self.regexes[0], &captures);
+                complete = self.until.is_none() && self.regexes.len() == 1;
+                self.pending = Some(pending);
+            }
+        }
+
+        if !complete {
+            return None;
+        }
+        let pending = self.pending.take()?;
+        self.fires += 1;
+        Some(alias::substitute_groups(
+            &self.config.action,
+            &pending.groups,
+            &pending.names,
+            &pending.matched.join(" "),
+            config,
+        ))
+    }
+}
+
+/// Parses `[-w <lines>] [-until <pattern>] [-g <group>] <pattern>... => <action>`,
+/// the patterns being regexes between slashes, quoted texts or words
+pub fn parse_definition(definition: &str) -> Result<MultiTriggerConfig, String> {
+    let mut config = MultiTriggerConfig::default();
+    let (patterns, action) = match definition.find("=>") {
+        Some(i) => (definition[..i].trim(), definition[i + 2..].trim()),
+        None => return Err(String::from("no action after =>")),
+    };
+    config.action = String::from(action);
+
+    let mut rest = patterns;
+    while rest.starts_with('-') {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let (flag, after) = (&rest[..end], rest[end..].trim_start());
+        rest = match flag {
+            "-until" => {
+                let (until, after) = next_pattern(after)?;
+                config.until = String::from(until);
+                after
+            }
+            "-w" | "-g" => {
+                let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                let value = &after[..end];
+                if flag == "-w" {
+                    let window = value
+                        .parse()
+                        .map_err(|_| format!("invalid window {}", value))?;
+                    config.window = Some(window);
+                } else {
+                    config.group = String::from(value);
+                }
+                after[end..].trim_start()
+            }
+            _ => return Err(format!("unknown option {}", flag)),
+        };
+    }
+
+    config.patterns.clear();
+    while !rest.is_empty() {
+        let (pattern, after) = next_pattern(rest)?;
+        config.patterns.push(String::from(pattern));
+        rest = after;
+    }
+    if config.patterns.is_empty() {
+        return Err(String::from("no pattern"));
+    }
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trigger(definition: &str) -> MultiTrigger {
+        MultiTrigger::new(parse_definition(definition).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn matches_a_sequence_within_a_window() {
+        let mut trigger = trigger(
+            "-w 3 /^Name: (\\w+)/ /^Level: (?P<level>\\d+)/ => say %1 is level %{level}",
+        );
+        let config = CommandConfig::default();
+
+        assert_eq!(trigger.feed("Name: Bob", &config), None);
+        assert_eq!(trigger.feed("Race: Elf", &config), None);
+        assert_eq!(
+            trigger.feed("Level: 12", &config),
+            Some(String::from("say Bob is level 12"))
+        );
+        assert_eq!(trigger.fires, 1);
+
+        trigger.feed("Name: Joe", &config);
+        trigger.feed("Race: Elf", &config);
+        trigger.feed("Class: Mage", &config);
+        assert_eq!(trigger.feed("Level: 3", &config), None);
+    }
+
+    #[test]
+    fn collects_until_the_closing_line() {
+        let mut trigger = trigger(
+            "-until \"End of list\" \"You are wearing:\" /<worn on (\\w+)>/ => say %1 %2 %3",
+        );
+        let config = CommandConfig::default();
+
+        assert_eq!(trigger.feed("You are wearing:", &config), None);
+        assert_eq!(trigger.feed("<worn on head> a hat", &config), None);
+        for _ in 0..20 {
+            assert_eq!(trigger.feed("a blank line", &config), None);
+        }
+        assert_eq!(trigger.feed("<worn on feet> boots", &config), None);
+        assert_eq!(
+            trigger.feed("End of list", &config),
+            Some(String::from("say head feet "))
+        );
+        assert_eq!(trigger.config.window(), 0);
+        assert_eq!(parse_definition("-w 5 -until end start => look").unwrap().window(), 5);
+    }
+}
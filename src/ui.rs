@@ -1,26 +1,58 @@
+use std::borrow::Cow;
 use std::io;
 
 pub mod app;
 pub mod app_events;
+pub mod commands;
+pub mod completion;
 pub mod events;
+pub mod history;
+pub mod line_editor;
+pub mod scrollback;
+pub mod search;
+pub mod wrap;
 
-use app::Message;
+use crate::ansi::StyledLine;
+use crate::color::downsample_style;
+use app::{LinkRef, Message};
+use search::Search;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+use wrap::{wrap, Row};
 use tui::backend::Backend;
 use tui::layout::{Constraint, Direction, Layout, Rect};
-use tui::style::{Color, Style};
+use tui::style::{Color, Modifier, Style};
 use tui::symbols::DOT;
 use tui::widgets::*;
 use tui::{Frame, Terminal};
 
 pub use app::{App, AppArea};
 
-pub fn draw_app<B: Backend>(terminal: &mut Terminal<B>, app: &App) -> Result<(), io::Error> {
-    terminal.draw(|mut f| {
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AppLayout {
+    pub main: Rect,
+    pub input: Rect,
+    pub map: Rect,
+    pub chat: Rect,
+    pub status: Rect,
+}
+
+fn contains(area: &Rect, x: u16, y: u16) -> bool {
+    x >= area.x && x < area.x + area.width && y >= area.y && y < area.y + area.height
+}
+
+impl AppLayout {
+    pub fn new(size: Rect) -> AppLayout {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0), Constraint::Length(1)].as_ref())
+            .split(size);
+
         let chunks = Layout::default()
             .direction(Direction::Horizontal)
             .margin(1)
             .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
-            .split(f.size());
+            .split(rows[0]);
 
         let left = chunks[0];
         let right = chunks[1];
@@ -37,11 +69,39 @@ pub fn draw_app<B: Backend>(terminal: &mut Terminal<B>, app: &App) -> Result<(),
             .constraints([Constraint::Percentage(40), Constraint::Percentage(60)].as_ref())
             .split(right);
 
-        draw_main(&mut f, left_chunks[0], app);
-        draw_input(&mut f, left_chunks[1], app);
+        AppLayout {
+            main: left_chunks[0],
+            input: left_chunks[1],
+            map: right_chunks[0],
+            chat: right_chunks[1],
+            status: rows[1],
+        }
+    }
+
+    pub fn area_at(&self, x: u16, y: u16) -> Option<AppArea> {
+        [
+            (self.main, AppArea::Main),
+            (self.input, AppArea::Input),
+            (self.map, AppArea::Map),
+            (self.chat, AppArea::Chat),
+        ]
+        .iter()
+        .find(|(rect, _)| contains(rect, x, y))
+        .map(|(_, area)| *area)
+    }
+}
+
+pub fn draw_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> Result<(), io::Error> {
+    app.layout = AppLayout::new(terminal.size()?);
+    let app: &App = app;
+
+    terminal.draw(|mut f| {
+        draw_main(&mut f, app.layout.main, app);
+        draw_input(&mut f, app.layout.input, app);
         //        //draw_character_sheet
-        draw_map(&mut f, right_chunks[0], app);
-        draw_chat(&mut f, right_chunks[1], app);
+        draw_map(&mut f, app.layout.map, app);
+        draw_chat(&mut f, app.layout.chat, app);
+        draw_status(&mut f, app.layout.status, app);
     })
 }
 
@@ -69,38 +129,303 @@ fn block(app: &App, area: AppArea) -> Block<'static> {
         .border_style(border_style(app, area));
 }
 
-fn draw_main<B: Backend>(f: &mut Frame<B>, area: Rect, app: &App) {
-    let t: Vec<Text> = app
-        .messages
-        .iter()
-        .map(|msg| -> Text {
-            let mut text: String;
-            match msg {
-                Message::UserInput(s) => {
-                    text = String::new();
-                    text.push_str("> ");
-                    text.push_str(s.replace("\r\n", "\n").as_str());
+fn link_style(style: Style, selected: bool) -> Style {
+    if selected {
+        style.modifier(style.modifier | Modifier::UNDERLINED | Modifier::REVERSED)
+    } else {
+        style.modifier(style.modifier | Modifier::UNDERLINED)
+    }
+}
+
+/// A line of the Main area with the rows it is wrapped into
+struct ViewLine<'a> {
+    index: usize,
+    line: Cow<'a, StyledLine>,
+    rows: Vec<Row>,
+}
+
+fn message_line(app: &App, index: usize) -> Option<Cow<StyledLine>> {
+    match app.scrollback.get(index) {
+        Some(Message::UserInput(s)) => Some(Cow::Owned(StyledLine::styled(
+            &format!("> {}", s),
+            Style::default(),
+        ))),
+        Some(Message::Network(line)) => Some(Cow::Borrowed(line)),
+        Some(Message::Info(s)) => Some(Cow::Owned(StyledLine::styled(
+            &format!("# {}", s),
+            Style::default().fg(Color::Cyan),
+        ))),
+        None => app.styled_line(index).map(Cow::Borrowed),
+    }
+}
+
+/// Lines displayed in the given area, the last one preceding `stop`.
+/// Only the last rows of the first line are kept when it does not fit entirely.
+fn view_lines(app: &App, stop: usize, area: Rect) -> Vec<ViewLine> {
+    let (width, height) = (area.width as usize, area.height as usize);
+    let mut lines = Vec::new();
+    let mut rows = 0;
+    let mut index = stop;
+
+    // only the visible lines are wrapped, so a resize re-flows them
+    while rows < height && index > app.scrollback.first() {
+        index -= 1;
+        if let Some(line) = message_line(app, index) {
+            let mut wrapped = wrap(&line, width, app.config.ui.indent);
+            rows += wrapped.len();
+            if rows > height {
+                wrapped.drain(..rows - height);
+            }
+            lines.push(ViewLine {
+                index,
+                line,
+                rows: wrapped,
+            });
+        }
+    }
+
+    lines.reverse();
+    lines
+}
+
+fn view_texts<'a>(app: &App, lines: &'a [ViewLine]) -> Vec<Text<'a>> {
+    let mut texts: Vec<Text> = Vec::new();
+    let depth = app.config.colors.render_depth();
+
+    for view in lines {
+        let selected = app.selected_link.and_then(|link| {
+            if link.line == view.index {
+                app.link(link)
+            } else {
+                None
+            }
+        });
+
+        let matches = match &app.search {
+            Some(search) => search.line_matches(view.index),
+            None => Vec::new(),
+        };
+        let highlights = app
+            .highlights
+            .ranges(&view.line.text(), |group| app.triggers.is_group_enabled(group));
+
+        // byte offset of the spans in the line
+        let offsets: Vec<usize> = view
+            .line
+            .spans
+            .iter()
+            .scan(0, |offset, span| {
+                let start = *offset;
+                *offset += span.text.len();
+                Some(start)
+            })
+            .collect();
+
+        for row in view.rows.iter() {
+            if !texts.is_empty() {
+                texts.push(Text::raw("\n"));
+            }
+            if row.indent > 0 {
+                texts.push(Text::raw(" ".repeat(row.indent)));
+            }
+
+            for piece in row.pieces.iter() {
+                let span = &view.line.spans[piece.span];
+                let style = match &span.link {
+                    Some(link) => link_style(span.style, selected == Some(link)),
+                    None => span.style,
+                };
+                let base = offsets[piece.span];
+                let (start, end) = (base + piece.start, base + piece.end);
+
+                // the piece is cut at the bounds of the highlights and search matches
+                let mut cuts = vec![start, end];
+                let bounds = highlights
+                    .iter()
+                    .map(|(s, e, _)| (*s, *e))
+                    .chain(matches.iter().map(|(m, _)| (m.start, m.end)));
+                for (s, e) in bounds {
+                    cuts.extend([s, e].iter().filter(|cut| start < **cut && **cut < end));
                 }
-                Message::Network(s) => {
-                    text = s.replace("\r\n", "\n"); //XXX TODO make if configurable
+                cuts.sort();
+                cuts.dedup();
+
+                for cut in cuts.windows(2) {
+                    let (s, e) = (cut[0], cut[1]);
+                    let covers = |(r_start, r_end): (usize, usize)| r_start <= s && e <= r_end;
+                    let style = match matches.iter().find(|(m, _)| covers((m.start, m.end))) {
+                        Some((_, current)) => search_style(*current),
+                        None => highlights
+                            .iter()
+                            .filter(|(h_start, h_end, _)| covers((*h_start, *h_end)))
+                            .fold(style, |style, (_, _, h)| {
+                                downsample_style(h.apply(style), depth)
+                            }),
+                    };
+                    texts.push(Text::styled(&span.text[s - base..e - base], style));
                 }
             }
-            Text::raw(text)
-        })
-        .collect();
+        }
+    }
+    texts
+}
+
+fn search_style(current: bool) -> Style {
+    if current {
+        Style::default().fg(Color::Black).bg(Color::LightYellow)
+    } else {
+        Style::default().fg(Color::Black).bg(Color::Yellow)
+    }
+}
+
+fn search_title(search: &Search) -> String {
+    let status = match search.position() {
+        _ if !search.valid => String::from("invalid pattern"),
+        (_, 0) => String::from("no match"),
+        (current, count) => format!("{}/{}", current, count),
+    };
+    let cursor = if search.editing { "_" } else { "" };
+    format!("search /{}{}/ {}", search.pattern, cursor, status)
+}
+
+/// Splits the inside of the Main area into the history view and, while scrolled back,
+/// the live view following the output
+pub fn main_views(app: &App) -> (Rect, Option<Rect>) {
+    let inner = Block::default().borders(Borders::ALL).inner(app.layout.main);
+    let split = app.config.ui.split.min(90);
+
+    if app.scrollback.is_following() || split == 0 || inner.height < 4 {
+        return (inner, None);
+    }
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(100 - split), Constraint::Percentage(split)].as_ref())
+        .split(inner);
+    (chunks[0], Some(chunks[1]))
+}
+
+fn live_block(app: &App) -> Block<'static> {
+    Block::default()
+        .borders(Borders::TOP)
+        .title("Live")
+        .border_style(border_style(app, AppArea::Main))
+}
+
+/// Areas of the Main views with the lines they display
+fn main_view_lines(app: &App) -> Vec<(Rect, Vec<ViewLine>)> {
+    let (history, live) = main_views(app);
+    let mut views = vec![(history, view_lines(app, app.scrollback.bottom(), history))];
+
+    if let Some(live) = live {
+        let live = live_block(app).inner(live);
+        views.push((live, view_lines(app, app.scrollback.end() + 1, live)));
+    }
+    views
+}
+
+/// Number of lines displayed, entirely or not, in the history view
+pub fn history_lines(app: &App) -> usize {
+    let (history, _) = main_views(app);
+    view_lines(app, app.scrollback.bottom(), history).len()
+}
+
+/// Link displayed at the given screen position
+pub fn link_at(app: &App, x: u16, y: u16) -> Option<LinkRef> {
+    let (area, lines) = main_view_lines(app)
+        .into_iter()
+        .find(|(area, _)| contains(area, x, y))?;
+
+    let (col, row) = ((x - area.x) as usize, (y - area.y) as usize);
+
+    let mut first_row = 0;
+    for view in lines.iter() {
+        if row >= first_row + view.rows.len() {
+            first_row += view.rows.len();
+            continue;
+        }
+
+        let wrapped = &view.rows[row - first_row];
+        let mut start = wrapped.indent;
+        for piece in wrapped.pieces.iter() {
+            let span = &view.line.spans[piece.span];
+            let end = start + span.text[piece.start..piece.end].width();
+            if col >= start && col < end {
+                return span.link.as_ref().map(|link| {
+                    // the link starts at its first span
+                    let first = (0..=piece.span)
+                        .rev()
+                        .take_while(|j| view.line.spans[*j].link.as_ref() == Some(link))
+                        .last()
+                        .unwrap_or(piece.span);
+                    LinkRef {
+                        line: view.index,
+                        span: first,
+                    }
+                });
+            }
+            start = end;
+        }
+        return None;
+    }
+    None
+}
 
-    let w = Paragraph::new(t.iter())
-        .raw(true)
-        //.style(Style::default().fg(Color::Yellow))
-        .block(block(app, AppArea::Main));
+fn draw_main<B: Backend>(f: &mut Frame<B>, area: Rect, app: &App) {
+    let mut title = String::from(AppArea::MAIN);
+    if let Some(search) = &app.search {
+        title.push_str(" - ");
+        title.push_str(&search_title(search));
+    }
+    match app.scrollback.offset() {
+        0 => (),
+        below => title.push_str(&format!(" - {} more below", below)),
+    }
+    f.render_widget(block(app, AppArea::Main).title(&title), area);
 
-    f.render_widget(w, area);
+    if let (_, Some(live)) = main_views(app) {
+        f.render_widget(live_block(app), live);
+    }
+
+    // the lines are already wrapped, the paragraph only truncates
+    for (view_area, lines) in main_view_lines(app) {
+        let t = view_texts(app, &lines);
+        f.render_widget(Paragraph::new(t.iter()), view_area);
+    }
 }
 
 fn draw_input<B: Backend>(f: &mut Frame<B>, area: Rect, app: &App) {
-    let t = [Text::raw(&app.input)];
+    let style = Style::default().fg(Color::Yellow);
+    let mut t: Vec<Text> = Vec::new();
+
+    let (view, cursor) = match &app.history_search {
+        Some(search) => {
+            let found = search.found.and_then(|i| app.history.get(i));
+            let view = format!("(reverse-i-search)`{}': {}", search.pattern, found.unwrap_or(""));
+            let cursor = view.len();
+            (view, cursor)
+        }
+        None => app
+            .input
+            .view(area.width.saturating_sub(2) as usize, app.server_echo),
+    };
+
+    if app.focused_area == AppArea::Input {
+        // the cursor is drawn on the grapheme following it
+        let (before, rest) = view.split_at(cursor);
+        let at = rest.graphemes(true).next().unwrap_or("");
+        t.push(Text::raw(before));
+        t.push(Text::styled(
+            if at.is_empty() { " " } else { at },
+            style.modifier(Modifier::REVERSED),
+        ));
+        t.push(Text::raw(&rest[at.len()..]));
+    } else {
+        t.push(Text::raw(view.as_str()));
+    }
+
     let w = Paragraph::new(t.iter())
-        .style(Style::default().fg(Color::Yellow))
+        .style(style)
         .block(block(app, AppArea::Input));
     f.render_widget(w, area);
 }
@@ -120,3 +445,13 @@ fn draw_chat<B: Backend>(f: &mut Frame<B>, area: Rect, app: &App) {
 
     f.render_widget(w, area)
 }
+
+fn draw_status<B: Backend>(f: &mut Frame<B>, area: Rect, app: &App) {
+    let latency = match app.session.borrow().latency {
+        Some(latency) => format!("{} ms", latency.as_millis()),
+        None => String::from("-"),
+    };
+    let t = [Text::raw(format!(" latency: {}", latency))];
+    let w = Paragraph::new(t.iter()).style(Style::default().fg(Color::Black).bg(Color::Gray));
+    f.render_widget(w, area);
+}
@@ -1,5 +1,37 @@
-use log::debug;
-use mudnet::CnxOutput;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Instant;
+
+use log::{debug, info, warn};
+use mudnet::mslp::Link;
+use mudnet::msdp::MsdpVal;
+use mudnet::{CnxOutput, NegotiationState};
+
+use crate::alias::{Alias, Aliases};
+use crate::ansi::{StyledLine, StyledParser};
+use crate::config::Config;
+use crate::gag::Rewrites;
+use crate::highlight::Highlights;
+use crate::script::{ScriptAction, Scripts, SCRIPTS_DIR};
+use crate::session::{Session, SharedSession};
+use crate::sound::{MediaPlayer, SoundHandler};
+use crate::speedwalk::Speedwalk;
+use crate::timer::Timers;
+use crate::trigger::{Trigger, Triggers};
+use crate::ui::commands;
+use crate::ui::completion::{self, Completion};
+use crate::ui::history::{History, HistorySearch};
+use crate::ui::line_editor::LineEditor;
+use crate::ui::scrollback::Scrollback;
+use crate::ui::search::{Search, SearchDirection};
+use crate::ui::{self, AppLayout};
+use crate::variable;
+use crate::world::{TimerConfig, TriggerConfig, WorldData, WORLD_FILE};
+
+const HISTORY_FILE: &'static str = "history";
+
+// number of scrollback lines the completion words are looked up in
+const COMPLETION_LINES: usize = 500;
 
 #[derive(PartialEq, Copy, Clone)]
 pub enum AppArea {
@@ -47,34 +79,547 @@ impl AppArea {
 }
 
 pub struct App {
+    pub config: Config,
     pub focused_area: AppArea,
+    /// Position of the areas on screen, updated on each draw
+    pub layout: AppLayout,
     /// Current value of the input box
-    pub input: String,
-    /// History of recorded messages
-    pub messages: Vec<Message>,
+    pub input: LineEditor,
+    /// Commands typed, saved in the world directory
+    pub history: History,
+    /// Definitions of the world, saved in its directory
+    pub world: WorldData,
+    pub aliases: Aliases,
+    pub triggers: Triggers,
+    /// Gags and substitutions of the server lines
+    pub rewrites: Rewrites,
+    /// Colors of the matching text, applied when the lines are displayed
+    pub highlights: Highlights,
+    pub timers: Timers,
+    /// The walk in progress, its steps following each other with a delay
+    pub speedwalk: Speedwalk,
+    /// Values reported by the server and variables, shared with the scripts
+    pub session: SharedSession,
+    pub scripts: Scripts,
+    /// Commands run by the triggers, the timers and the scripts, sent once the server output is applied
+    pub pending_commands: Vec<String>,
+    /// Last prompt the triggers were matched against, not to fire again on the same prompt
+    last_prompt: String,
+    /// Completion of the word before the cursor, while Tab is pressed
+    pub completion: Option<Completion>,
+    /// Commands the server reported through MSDP
+    pub msdp_commands: Vec<String>,
+    /// Reverse search in the history, replacing the input while active
+    pub history_search: Option<HistorySearch>,
+    /// The server echoes the input itself, the input is a password not to be recorded
+    pub server_echo: bool,
+    /// A connection is open, or being opened
+    pub connected: bool,
+    /// Address to connect to, opened by the main loop
+    pub connection_request: Option<String>,
+    /// Last recorded messages
+    pub scrollback: Scrollback<Message>,
+    /// Turns the server data into styled lines
+    parser: StyledParser,
+    /// The line still being received, usually a prompt, gagged and substituted like the others
+    prompt: StyledLine,
+    /// Search in the scrollback
+    pub search: Option<Search>,
+    /// Link selected in the Main area
+    pub selected_link: Option<LinkRef>,
+    /// Plays the MSP triggers
+    sound: Box<dyn SoundHandler>,
 }
 
 pub enum Message {
     UserInput(String),
-    Network(String),
+    Network(StyledLine),
+    /// Feedback of the client commands
+    Info(String),
+}
+
+/// Position of a link: index of its line in the scrollback, `scrollback.end()` being the pending line,
+/// and index of its first span in the line
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct LinkRef {
+    pub line: usize,
+    pub span: usize,
 }
 
 impl App {
-    pub fn new() -> App {
+    pub fn new(config: Config) -> App {
+        let sound = Box::new(MediaPlayer::new(&config.sound));
+        let parser = StyledParser::new(config.colors.render_depth());
+        let scrollback = Scrollback::new(config.ui.scrollback);
+        let history_file = config.world.dir().join(HISTORY_FILE);
+        let history = History::load(&history_file, config.ui.history).unwrap_or_else(|e| {
+            warn!("failed to read the history {:?} : {}", history_file, e);
+            History::new(config.ui.history)
+        });
+        let world_file = config.world.dir().join(WORLD_FILE);
+        let world = WorldData::load(&world_file).unwrap_or_else(|e| {
+            warn!("failed to read the world {:?} : {}", world_file, e);
+            WorldData::default()
+        });
+        let aliases = Aliases::from_config(&world.aliases);
+        let triggers =
+            Triggers::from_config(&world.triggers, &world.multi_triggers, &world.disabled_groups);
+        let rewrites = Rewrites::from_config(&world.gags, &world.substitutions);
+        let highlights = Highlights::from_config(&world.highlights);
+        let timers = Timers::from_config(&world.timers, Instant::now());
+        let speedwalk = Speedwalk::new(&config.speedwalk, config.commands.max_repeat);
+        let session = Rc::new(RefCell::new(Session {
+            variables: world.variables.clone(),
+            ..Session::default()
+        }));
+        // loaded on the first tick
+        let scripts = Scripts::new(config.world.dir().join(SCRIPTS_DIR), &session);
         App {
+            config,
             focused_area: AppArea::Input,
-            input: String::new(),
-            messages: Vec::new(),
+            layout: AppLayout::default(),
+            input: LineEditor::new(),
+            history,
+            world,
+            aliases,
+            triggers,
+            rewrites,
+            highlights,
+            timers,
+            speedwalk,
+            session,
+            scripts,
+            pending_commands: Vec::new(),
+            last_prompt: String::new(),
+            history_search: None,
+            completion: None,
+            msdp_commands: Vec::new(),
+            server_echo: false,
+            connected: false,
+            connection_request: None,
+            scrollback,
+            parser,
+            prompt: StyledLine::new(),
+            search: None,
+            selected_link: None,
+            sound,
+        }
+    }
+
+    pub fn set_sound_handler(&mut self, handler: Box<dyn SoundHandler>) {
+        self.sound = handler;
+    }
+
+    /// The server line at the given index, `scrollback.end()` being the line still being received
+    pub fn styled_line(&self, index: usize) -> Option<&StyledLine> {
+        match self.scrollback.get(index) {
+            Some(Message::Network(line)) => Some(line),
+            Some(_) => None,
+            None if index == self.scrollback.end() => Some(&self.prompt),
+            None => None,
+        }
+    }
+
+    pub fn link(&self, link: LinkRef) -> Option<&Link> {
+        self.styled_line(link.line)?
+            .spans
+            .get(link.span)?
+            .link
+            .as_ref()
+    }
+
+    fn link_refs(&self) -> Vec<LinkRef> {
+        let mut links = Vec::new();
+        for i in self.scrollback.first()..=self.scrollback.end() {
+            if let Some(line) = self.styled_line(i) {
+                for (j, span) in line.spans.iter().enumerate() {
+                    // a link made of several spans is selected as a whole
+                    let continued = j > 0 && line.spans[j - 1].link == span.link;
+                    if span.link.is_some() && !continued {
+                        links.push(LinkRef { line: i, span: j });
+                    }
+                }
+            }
+        }
+        links
+    }
+
+    /// Selects the link following the selected one, the most recent one if none is selected
+    pub fn select_next_link(&mut self) {
+        let links = self.link_refs();
+        self.selected_link = match self.selected_link {
+            Some(selected) => links
+                .iter()
+                .find(|i| **i > selected)
+                .or(links.first())
+                .cloned(),
+            None => links.last().cloned(),
+        }
+    }
+
+    /// Selects the link preceding the selected one, the most recent one if none is selected
+    pub fn select_previous_link(&mut self) {
+        let links = self.link_refs();
+        self.selected_link = match self.selected_link {
+            Some(selected) => links
+                .iter()
+                .rev()
+                .find(|i| **i < selected)
+                .or(links.last())
+                .cloned(),
+            None => links.last().cloned(),
+        }
+    }
+
+    /// Number of rows of the Main area history view
+    pub fn page_height(&self) -> usize {
+        ui::main_views(self).0.height as usize
+    }
+
+    /// Scrolls up so that the top line of the view ends at the bottom
+    pub fn page_up(&mut self) {
+        let (height, lines) = (self.page_height(), ui::history_lines(self));
+        self.scrollback.scroll_up(lines.max(2) - 1, height);
+    }
+
+    pub fn page_down(&mut self) {
+        let lines = ui::history_lines(self);
+        self.scrollback.scroll_down(lines.max(2) - 1);
+    }
+
+    /// Starts a search from the bottom line of the view, or its top line for a forward search
+    pub fn start_search(&mut self, direction: SearchDirection) {
+        let visible = self.scrollback.visible(self.page_height());
+        let origin = match direction {
+            SearchDirection::Backward => visible.end.saturating_sub(1),
+            SearchDirection::Forward => visible.start,
+        };
+        self.search = Some(Search::new(direction, origin));
+    }
+
+    /// Searches the pattern again, after it was edited
+    pub fn update_search(&mut self) {
+        let texts: Vec<(usize, String)> = self
+            .scrollback
+            .iter()
+            .filter_map(|(i, msg)| match msg {
+                Message::Network(line) => Some((i, line.text())),
+                _ => None,
+            })
+            .collect();
+
+        if let Some(search) = self.search.as_mut() {
+            search.update(texts.iter().map(|(i, text)| (*i, text.as_str())));
+        }
+        self.show_search_match();
+    }
+
+    pub fn search_step(&mut self, direction: SearchDirection) {
+        if let Some(search) = self.search.as_mut() {
+            search.step(direction);
+        }
+        self.show_search_match();
+    }
+
+    fn show_search_match(&mut self) {
+        if let Some(m) = self.search.as_ref().and_then(|search| search.current()) {
+            let height = self.page_height();
+            self.scrollback.scroll_to(m.line, height);
+        }
+    }
+
+    /// Shows a feedback of the client in the Main area
+    pub fn info(&mut self, text: String) {
+        self.scrollback.push(Message::Info(text));
+    }
+
+    /// Saves the definitions of the world after a change
+    pub fn save_world(&mut self) {
+        self.world.aliases = self.aliases.to_config();
+        self.world.triggers = self.triggers.to_config();
+        self.world.multi_triggers = self.triggers.to_multi_config();
+        self.world.gags = self.rewrites.to_gag_config();
+        self.world.substitutions = self.rewrites.to_substitution_config();
+        self.world.highlights = self.highlights.to_config();
+        self.world.timers = self.timers.to_config();
+        self.world.disabled_groups = self.triggers.disabled_groups();
+        self.world.variables = self.session.borrow().variables.clone();
+        if let Err(e) = self.world.save() {
+            warn!("failed to save the world : {}", e);
+            self.info(format!("failed to save the world : {}", e));
+        }
+    }
+
+    /// Words the input is completed with, the most relevant first
+    fn completion_words(&self) -> Vec<String> {
+        let prefix = &self.config.commands.prefix;
+        let mut words: Vec<String> = commands::COMMANDS
+            .iter()
+            .map(|c| format!("{}{}", prefix, c.name))
+            .collect();
+        words.extend(self.aliases.iter().filter_map(|a| a.word()).map(String::from));
+        words.extend(self.msdp_commands.iter().cloned());
+
+        // the most recent lines first
+        let first = self.scrollback.end().saturating_sub(COMPLETION_LINES);
+        for i in (first..self.scrollback.end()).rev() {
+            if let Some(line) = self.styled_line(i) {
+                words.extend(completion::words(&line.text()).map(String::from));
+            }
         }
+        words
+    }
+
+    /// Completes the word before the cursor, or cycles through the candidates on the next calls
+    pub fn complete(&mut self, forward: bool) {
+        if self.completion.is_none() {
+            let (start, word) = self.input.current_word();
+            if word.is_empty() {
+                return;
+            }
+            let words = self.completion_words();
+            let candidates = completion::candidates(word, words.iter().map(String::as_str));
+            self.completion = Some(Completion::new(start, word, candidates));
+        }
+
+        if let Some(completion) = self.completion.as_mut() {
+            let start = completion.start();
+            let word = if forward {
+                completion.next()
+            } else {
+                completion.previous()
+            };
+            self.input.replace_before_cursor(start, word);
+        }
+    }
+
+    /// Queues the actions of the triggers matching a line
+    fn run_triggers(&mut self, text: &str, prompt: bool) {
+        let count = self.triggers.iter().count();
+        let actions = self.triggers.fire(text, prompt, &self.config.commands);
+        self.pending_commands.extend(actions);
+        // one-shot triggers were removed
+        if self.triggers.iter().count() != count {
+            self.save_world();
+        }
+    }
+
+    /// Queues the commands of the timers due, and the next step of the walk
+    pub fn run_timers(&mut self) {
+        let now = Instant::now();
+        let commands = self.timers.run(now);
+        self.pending_commands.extend(commands);
+        if let Some(step) = self.speedwalk.run(now) {
+            self.pending_commands.push(step);
+        }
+    }
+
+    /// Shows the errors of the scripts, which go on running
+    fn script_errors(&mut self, errors: Vec<String>) {
+        for error in errors {
+            warn!("script error : {}", error);
+            self.info(format!("script error : {}", error));
+        }
+    }
+
+    /// Applies what the scripts asked for, their aliases, triggers and timers not being saved
+    /// nor replacing the ones of the user
+    fn apply_script_actions(&mut self) {
+        for action in self.scripts.take_actions() {
+            match action {
+                ScriptAction::Send(command) => self.pending_commands.push(command),
+                ScriptAction::Echo(text) => self.info(text),
+                ScriptAction::Alias { pattern, .. } if self.aliases.is_user_defined(&pattern) => {
+                    self.info(format!("alias {} of the user kept over the script", pattern))
+                }
+                ScriptAction::Alias { pattern, body } => match Alias::new(&pattern, &body) {
+                    Ok(alias) => self.aliases.set(alias.scripted()),
+                    Err(e) => self.info(format!("invalid alias {} : {}", pattern, e)),
+                },
+                ScriptAction::Trigger { pattern, .. }
+                    if self.triggers.is_user_defined(&pattern) =>
+                {
+                    self.info(format!("trigger {} of the user kept over the script", pattern))
+                }
+                ScriptAction::Trigger { pattern, action } => {
+                    let config = TriggerConfig {
+                        pattern,
+                        action,
+                        scripted: true,
+                        ..TriggerConfig::default()
+                    };
+                    match Trigger::new(config) {
+                        Ok(trigger) => self.triggers.set(trigger),
+                        Err(e) => self.info(format!("invalid trigger : {}", e)),
+                    }
+                }
+                ScriptAction::Timer { name, .. } if self.timers.is_user_defined(&name) => {
+                    self.info(format!("timer {} of the user kept over the script", name))
+                }
+                ScriptAction::Timer {
+                    name,
+                    seconds,
+                    command,
+                } => {
+                    let config = TimerConfig {
+                        name,
+                        seconds,
+                        command,
+                        scripted: true,
+                        ..TimerConfig::default()
+                    };
+                    self.timers.add(config, Instant::now());
+                }
+                ScriptAction::Reset => {
+                    self.aliases.remove_scripted();
+                    self.triggers.remove_scripted();
+                    self.timers.remove_scripted();
+                }
+            }
+        }
+        // the variables set by the scripts
+        if self.session.borrow().variables != self.world.variables {
+            self.save_world();
+        }
+    }
+
+    /// Replaces the variables of a command, e.g. `kill $target`
+    pub fn interpolate(&self, command: &str) -> String {
+        let session = self.session.borrow();
+        variable::interpolate(command, |name| session.variable(name))
+    }
+
+    /// Loads the changed scripts, all of them when forced
+    pub fn reload_scripts(&mut self, force: bool) {
+        let errors = self.scripts.reload(Instant::now(), force);
+        self.script_errors(errors);
+        self.apply_script_actions();
+    }
+
+    /// Calls a function of the scripts, e.g. from a trigger with `:call heal %1`
+    pub fn call_script(&mut self, name: &str, args: Vec<String>) {
+        if let Err(e) = self.scripts.call(name, args) {
+            self.script_errors(vec![e]);
+        }
+        self.apply_script_actions();
+    }
+
+    fn push_lines(&mut self, lines: Vec<StyledLine>) {
+        for mut line in lines {
+            let text = line.text();
+            if self.config.ui.raw_log {
+                info!(target: "raw", "{}", text);
+            }
+            // the triggers see the lines as sent by the server
+            self.run_triggers(&text, false);
+            let errors = self.scripts.on_line(&text);
+            self.script_errors(errors);
+            self.last_prompt.clear();
+
+            let triggers = &self.triggers;
+            let enabled = |group: &str| triggers.is_group_enabled(group);
+            if !self.rewrites.is_gagged(&text, enabled) {
+                self.rewrites.substitute(&mut line, enabled);
+                self.scrollback.push(Message::Network(line))
+            }
+            // the echoes of the scripts follow the line
+            self.apply_script_actions();
+            let dropped = self.speedwalk.check_failure(&text);
+            if dropped > 0 {
+                self.info(format!("walk stopped, {} steps left", dropped));
+            }
+        }
+
+        let prompt = self.parser.pending().text();
+        if !prompt.is_empty() && prompt != self.last_prompt {
+            self.run_triggers(&prompt, true);
+            self.last_prompt = prompt;
+        }
+        self.rewrite_prompt();
+    }
+
+    /// Applies the gags and substitutions to the line still being received
+    fn rewrite_prompt(&mut self) {
+        let mut prompt = self.parser.pending().clone();
+        let triggers = &self.triggers;
+        let enabled = |group: &str| triggers.is_group_enabled(group);
+        if self.rewrites.is_gagged(&prompt.text(), enabled) {
+            prompt = StyledLine::new();
+        } else {
+            self.rewrites.substitute(&mut prompt, enabled);
+        }
+        self.prompt = prompt;
     }
 
     pub fn apply_event(&mut self, event: CnxOutput) {
         match event {
             CnxOutput::Data(msg) => {
                 debug!("apply_event : {}", msg);
-                self.messages.push(Message::Network(msg))
+                let lines = self.parser.feed(&msg);
+                self.push_lines(lines);
+            }
+            CnxOutput::Link(link) => {
+                let lines = self.parser.feed_link(&link);
+                self.push_lines(lines);
+            }
+            CnxOutput::Msdp(data) => {
+                debug!("msdp : {} {:?}", data.key, data.value);
+                if let ("COMMANDS", MsdpVal::Array(commands)) = (data.key.as_str(), &data.value) {
+                    self.msdp_commands = commands
+                        .iter()
+                        .filter_map(|command| match command {
+                            MsdpVal::Value(command) => Some(command.clone()),
+                            _ => None,
+                        })
+                        .collect()
+                }
+                self.session.borrow_mut().msdp.insert(data.key, data.value);
+            }
+            CnxOutput::Latency(latency) => self.session.borrow_mut().latency = Some(latency),
+            CnxOutput::ServerEcho(echo) => self.server_echo = echo,
+            CnxOutput::Atcp(msg) => {
+                debug!("atcp : {:?}", msg);
+                let name = format!("{}.{}", msg.module, msg.message);
+                self.session.borrow_mut().atcp.insert(name, msg.value);
+            }
+            CnxOutput::Gmcp(msg) => {
+                debug!("gmcp : {:?}", msg);
+                self.session.borrow_mut().gmcp.insert(msg.package, msg.data);
+            }
+            CnxOutput::Aardwolf(msg) => debug!("aardwolf 102 : {:?}", msg),
+            CnxOutput::Sound(sound) => {
+                if let Err(e) = self.sound.handle(&sound) {
+                    warn!("failed to play {:?} : {}", sound, e);
+                }
             }
-            CnxOutput::Msdp(_) => (),
+            CnxOutput::Options(options) => self.show_options(&options),
+            CnxOutput::Connected(address) => self.info(format!("connected to {}", address)),
+            CnxOutput::ConnectionFailed(e) => {
+                self.connected = false;
+                self.info(format!("failed to connect : {}", e));
+            }
+            CnxOutput::Disconnected => {
+                self.connected = false;
+                self.server_echo = false;
+                self.info(String::from("disconnected"));
+            }
+        }
+    }
+
+    fn show_options(&mut self, options: &[NegotiationState]) {
+        if options.is_empty() {
+            self.info(String::from("no telnet option negotiated"));
+        }
+        for state in options {
+            let status = if state.is_active() {
+                "active"
+            } else if state.received_dont || state.received_wont || state.send_wont {
+                "refused"
+            } else {
+                "pending"
+            };
+            let code = state.option.to_byte();
+            self.info(format!("{} ({}) : {}", commands::option_name(code), code, status));
         }
     }
 }
@@ -1,67 +1,1048 @@
+use std::process::{Command, Stdio};
+use std::time::Instant;
+
 use super::{App, AppArea};
-use crate::ui::app::Message;
-use crossterm::event::{KeyCode, KeyEvent};
-use log::debug;
-use tokio::sync::mpsc::Sender;
+use crate::alias::{self, Alias};
+use crate::command;
+use crate::gag::{Gag, Substitution};
+use crate::highlight::Highlight;
+use crate::timer;
+use crate::trigger::multiline::{self, MultiTrigger};
+use crate::trigger::{self, Trigger};
+use crate::ui;
+use crate::ui::app::{LinkRef, Message};
+use crate::ui::commands;
+use crate::ui::history::HistorySearch;
+use crate::ui::search::SearchDirection;
+use crate::variable;
+use crate::world::{GagConfig, HighlightConfig, SubstitutionConfig, TimerConfig};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent};
+use log::{debug, warn};
+use mudnet::mslp::LinkTarget;
+use mudnet::{CnxInput, CnxOutput};
+use telnet::{NegotiationAction, TelnetOption};
+use tokio::sync::mpsc::{self, Sender};
 
 pub type ShouldQuit = bool;
 
 pub const SHOULD_QUIT: bool = true;
 
+// rows scrolled by a mouse wheel step
+const SCROLL_ROWS: usize = 3;
+
 pub async fn handle_string(
     app: &mut App,
-    command_sender: &mut Sender<String>,
+    command_sender: &mut Sender<CnxInput>,
     input: String,
 ) -> ShouldQuit {
     debug!("read {:?}", input);
 
-    let trimmed = input.trim();
+    // a password is sent as typed and not shown
+    if app.server_echo {
+        send_input(app, command_sender, CnxInput::Line(input)).await;
+        return false;
+    }
 
-    if trimmed == ":q" {
-        true
+    let config = &app.config.commands;
+    // a client command is not split, e.g. `:trigger hungry eat;drink` keeping its whole action
+    let split = if commands::is_client_command(&input, &config.prefix) {
+        vec![input]
     } else {
-        command_sender.send(input.clone()).await;
-        app.messages.push(Message::UserInput(input));
-        false
-    }
-    // else if trimmed == ":n" {} else if trimmed == ":ttype" {
-    //     mudnet::negotiate(&mut telnet, &mut cnx_state, &TelnetOption::TTYPE)?;
-    // } else if trimmed == ":gmcp" {
-    //     mudnet::negotiate(&mut telnet, &mut cnx_state, &TelnetOption::UnknownOption(mudnet::mud::options::GMCP))?;
-    // } else if trimmed == ":list" {
-    //     mudnet::gmcp::list_command(&mut telnet)?;
-    // }
-    // else if trimmed.len() > 0 {
-    //     telnet.write(input.as_bytes())?;
-    // } else {
-    //     debug!("writing nothing !")
-    // }
+        command::split(&input, &config.separator)
+    };
+    let mut typed_commands = Vec::new();
+    for command in split {
+        // a delayed walk only gives its first step, the others follow on the ticks
+        match app.speedwalk.expand(&command) {
+            Some(steps) => typed_commands.extend(app.speedwalk.start(steps, Instant::now())),
+            None => typed_commands.extend(command::repeated(&command, config.max_repeat)),
+        }
+    }
+
+    let prefix = app.config.commands.prefix.clone();
+    for typed in typed_commands {
+        // the client commands are not aliased, but aliases may expand to client commands
+        let commands = if commands::is_client_command(&typed, &prefix) {
+            vec![typed]
+        } else {
+            app.aliases.expand(&typed, &app.config.commands, &app.speedwalk)
+        };
+        for command in commands {
+            if commands::is_client_command(&command, &prefix) {
+                app.scrollback.push(Message::UserInput(command.clone()));
+                if client_command(app, command_sender, &command).await {
+                    return SHOULD_QUIT;
+                }
+                continue;
+            }
+            // a walk in the body of an alias
+            let steps = match app.speedwalk.expand(&command) {
+                Some(steps) => app.speedwalk.start(steps, Instant::now()),
+                None => vec![command],
+            };
+            for step in steps {
+                // the client commands keep the variables, e.g. in the body of an alias
+                let command = app.interpolate(commands::unescape(&step, &prefix));
+                app.scrollback.push(Message::UserInput(command.clone()));
+                send_input(app, command_sender, CnxInput::Line(command)).await;
+            }
+        }
+    }
+    false
 }
 
-pub async fn handle_key_event(
+/// Sends to the server, telling when there is no connection
+async fn send_input(app: &mut App, command_sender: &mut Sender<CnxInput>, input: CnxInput) {
+    if command_sender.send(input).await.is_err() {
+        app.info(String::from("not connected"));
+    }
+}
+
+/// Shows how a command is typed
+fn usage(app: &mut App, name: &str) {
+    if let Some(command) = commands::find(name) {
+        let prefix = &app.config.commands.prefix;
+        let usage = format!("usage : {}{} {}", prefix, command.name, command.args);
+        app.info(String::from(usage.trim_end()));
+    }
+}
+
+/// The quoted arguments of a command, the errors being shown
+fn arguments(app: &mut App, args: &str) -> Option<Vec<String>> {
+    match commands::tokenize(args) {
+        Ok(words) => Some(words),
+        Err(e) => {
+            app.info(format!("invalid arguments {} : {}", args, e));
+            None
+        }
+    }
+}
+
+/// Runs a command of the client, such as `:alias k kill %1`
+async fn client_command(
+    app: &mut App,
+    command_sender: &mut Sender<CnxInput>,
+    command: &str,
+) -> ShouldQuit {
+    let prefix = app.config.commands.prefix.clone();
+    let (name, args) = commands::parse(command, &prefix);
+
+    match name {
+        "q" => return SHOULD_QUIT,
+        "alias" => alias_command(app, args),
+        "unalias" => {
+            if app.aliases.remove(args) {
+                app.save_world();
+                app.info(format!("alias {} removed", args));
+            } else {
+                app.info(format!("no alias {}", args));
+            }
+        }
+        "trigger" => trigger_command(app, args),
+        "mtrigger" => multi_trigger_command(app, args),
+        "untrigger" => {
+            if app.triggers.remove(args) {
+                app.save_world();
+                app.info(format!("trigger {} removed", args));
+            } else {
+                app.info(format!("no trigger {}", args));
+            }
+        }
+        // `tgroup` is the name the command had when it only turned triggers on or off
+        "group" | "tgroup" => match args.split_whitespace().collect::<Vec<&str>>().as_slice() {
+            [group, state] if *state == "on" || *state == "off" => {
+                app.triggers.set_group_enabled(group, *state == "on");
+                app.save_world();
+                app.info(format!("group {} {}", group, state));
+            }
+            _ => usage(app, name),
+        },
+        "gag" => gag_command(app, args),
+        "ungag" => {
+            if app.rewrites.remove_gag(args) {
+                app.save_world();
+                app.info(format!("gag {} removed", args));
+            } else {
+                app.info(format!("no gag {}", args));
+            }
+        }
+        "delay" => {
+            let (seconds, command) = alias::split_definition(args);
+            match seconds.parse::<f64>() {
+                Ok(seconds) if !command.is_empty() => match timer::check_delay(seconds) {
+                    Ok(seconds) => {
+                        let name = app.timers.delay(seconds, command, Instant::now());
+                        app.info(format!("{} : {} in {}s", name, command, seconds));
+                    }
+                    Err(e) => app.info(e),
+                },
+                _ => usage(app, "delay"),
+            }
+        }
+        "timer" => timer_command(app, args),
+        "set" => set_command(app, args),
+        "unset" => {
+            if app.session.borrow_mut().variables.remove(args).is_some() {
+                app.save_world();
+                app.info(format!("variable {} removed", args));
+            } else {
+                app.info(format!("no variable {}", args));
+            }
+        }
+        "call" => {
+            if let Some(words) = arguments(app, args) {
+                let mut words = words.into_iter();
+                match words.next() {
+                    Some(function) => app.call_script(&function, words.collect()),
+                    None => usage(app, "call"),
+                }
+            }
+        }
+        "script" => match args {
+            "" => {
+                let scripts: Vec<String> = app
+                    .scripts
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect();
+                if scripts.is_empty() {
+                    app.info(String::from("no script"));
+                }
+                scripts.into_iter().for_each(|s| app.info(s));
+            }
+            "reload" => {
+                app.reload_scripts(true);
+                app.info(String::from("scripts reloaded"));
+            }
+            _ => usage(app, "script"),
+        },
+        "highlight" => highlight_command(app, args),
+        "unhighlight" => {
+            if app.highlights.remove(args) {
+                app.save_world();
+                app.info(format!("highlight {} removed", args));
+            } else {
+                app.info(format!("no highlight {}", args));
+            }
+        }
+        "subst" => substitution_command(app, args),
+        "unsubst" => {
+            if app.rewrites.remove_substitution(args) {
+                app.save_world();
+                app.info(format!("substitution {} removed", args));
+            } else {
+                app.info(format!("no substitution {}", args));
+            }
+        }
+        "help" => help_command(app, args),
+        "connect" => connect_command(app, args),
+        "disconnect" => send_input(app, command_sender, CnxInput::Disconnect).await,
+        "options" => send_input(app, command_sender, CnxInput::Options).await,
+        "telnet" => telnet_command(app, command_sender, args).await,
+        "gmcp" => match args {
+            "" => usage(app, "gmcp"),
+            message => {
+                let input = CnxInput::Gmcp(String::from(message));
+                send_input(app, command_sender, input).await
+            }
+        },
+        "msdp" => match arguments(app, args).as_ref().map(Vec::as_slice) {
+            Some([variable, value]) => {
+                let input = CnxInput::Msdp(variable.clone(), value.clone());
+                send_input(app, command_sender, input).await
+            }
+            Some(_) => usage(app, "msdp"),
+            None => (),
+        },
+        _ => app.info(format!("unknown command {}{}, see {}help", prefix, name, prefix)),
+    }
+    false
+}
+
+/// Lists the commands with their arguments, or describes one: `:help alias`
+fn help_command(app: &mut App, args: &str) {
+    let prefix = app.config.commands.prefix.clone();
+    let name = commands::parse(args, &prefix).0;
+    let shown: Vec<&commands::CommandInfo> = match name {
+        "" => commands::COMMANDS.iter().collect(),
+        name => commands::find(name).into_iter().collect(),
+    };
+    if shown.is_empty() {
+        app.info(format!("unknown command {}{}", prefix, name));
+    }
+    for command in shown {
+        let usage = format!("{}{} {}", prefix, command.name, command.args);
+        app.info(format!("{} : {}", usage.trim_end(), command.help));
+    }
+}
+
+/// Asks for a connection to the world, or to the given server
+fn connect_command(app: &mut App, args: &str) {
+    if app.connected {
+        let message = format!("already connected, {}disconnect first", app.config.commands.prefix);
+        app.info(message);
+        return;
+    }
+    let address = match arguments(app, args).as_ref().map(Vec::as_slice) {
+        Some([]) => app.config.world.address(),
+        Some([host, port]) => format!("{}:{}", host, port),
+        Some(_) => return usage(app, "connect"),
+        None => return,
+    };
+    app.connection_request = Some(address);
+}
+
+/// Sends a negotiation, `:telnet do gmcp`, or a subnegotiation, `:telnet sb gmcp 'Core.Ping'`
+async fn telnet_command(app: &mut App, command_sender: &mut Sender<CnxInput>, args: &str) {
+    let words = match arguments(app, args) {
+        Some(words) => words,
+        None => return,
+    };
+    let (action, name, data) = match words.as_slice() {
+        [action, name] => (action.as_str(), name, None),
+        [action, name, data] if action == "sb" => (action.as_str(), name, Some(data)),
+        _ => return usage(app, "telnet"),
+    };
+    let option = match commands::option_code(name) {
+        Some(code) => TelnetOption::parse(code),
+        None => {
+            app.info(format!("unknown telnet option {}", name));
+            return;
+        }
+    };
+    let input = match (action, data) {
+        ("do", None) => CnxInput::Negotiate(NegotiationAction::Do, option),
+        ("dont", None) => CnxInput::Negotiate(NegotiationAction::Dont, option),
+        ("will", None) => CnxInput::Negotiate(NegotiationAction::Will, option),
+        ("wont", None) => CnxInput::Negotiate(NegotiationAction::Wont, option),
+        ("sb", Some(data)) => CnxInput::Subnegotiate(option, data.clone().into_bytes()),
+        _ => return usage(app, "telnet"),
+    };
+    send_input(app, command_sender, input).await;
+}
+
+/// Lists the aliases, shows one of them or defines one
+fn alias_command(app: &mut App, args: &str) {
+    let (pattern, body) = alias::split_definition(args);
+    if pattern.is_empty() {
+        let aliases: Vec<String> = app
+            .aliases
+            .iter()
+            .map(|a| format!("{} = {}", a.pattern(), a.body()))
+            .collect();
+        if aliases.is_empty() {
+            app.info(String::from("no alias"));
+        }
+        aliases.into_iter().for_each(|a| app.info(a));
+    } else if body.is_empty() {
+        let shown = match app.aliases.get(pattern) {
+            Some(alias) => format!("{} = {}", alias.pattern(), alias.body()),
+            None => format!("no alias {}", pattern),
+        };
+        app.info(shown);
+    } else {
+        match Alias::new(pattern, body) {
+            Ok(alias) => {
+                app.aliases.set(alias);
+                app.save_world();
+                app.info(format!("alias {} = {}", pattern, body));
+            }
+            Err(e) => app.info(format!("invalid alias {} : {}", pattern, e)),
+        }
+    }
+}
+
+/// Lists the variables, shows one of them or sets one: `:set target goblin`
+fn set_command(app: &mut App, args: &str) {
+    let (name, value) = alias::split_definition(args);
+    if name.is_empty() {
+        let variables: Vec<String> = app
+            .session
+            .borrow()
+            .variables
+            .iter()
+            .map(|(name, value)| format!("{} = {}", name, value))
+            .collect();
+        if variables.is_empty() {
+            app.info(String::from("no variable"));
+        }
+        variables.into_iter().for_each(|v| app.info(v));
+    } else if value.is_empty() {
+        let shown = match app.session.borrow().variable(name) {
+            Some(value) => format!("{} = {}", name, value),
+            None => format!("no variable {}", name),
+        };
+        app.info(shown);
+    } else if !variable::is_valid_name(name) {
+        app.info(format!(
+            "invalid variable name {}, the uppercase names are read-only",
+            name
+        ));
+    } else {
+        app.session
+            .borrow_mut()
+            .variables
+            .insert(String::from(name), String::from(value));
+        app.save_world();
+        app.info(format!("{} = {}", name, value));
+    }
+}
+
+/// Lists the triggers or defines one
+fn trigger_command(app: &mut App, args: &str) {
+    if args.is_empty() {
+        let triggers: Vec<String> = app
+            .triggers
+            .iter()
+            .map(|t| {
+                let state = if app.triggers.is_enabled(t) { "" } else { " (off)" };
+                format!(
+                    "{} = {} [group {:?}, priority {}, fired {}]{}",
+                    t.config.pattern,
+                    t.config.action,
+                    t.config.group,
+                    t.config.priority,
+                    t.fires,
+                    state
+                )
+            })
+            .collect();
+        if triggers.is_empty() {
+            app.info(String::from("no trigger"));
+        }
+        triggers.into_iter().for_each(|t| app.info(t));
+        return;
+    }
+
+    let definition = trigger::parse_definition(args)
+        .and_then(|config| Trigger::new(config).map_err(|e| e.to_string()));
+    match definition {
+        Ok(trigger) => {
+            let shown = format!("trigger {} = {}", trigger.config.pattern, trigger.config.action);
+            app.triggers.set(trigger);
+            app.save_world();
+            app.info(shown);
+        }
+        Err(e) => app.info(format!("invalid trigger : {}", e)),
+    }
+}
+
+/// Lists the multi-line triggers or defines one
+fn multi_trigger_command(app: &mut App, args: &str) {
+    if args.is_empty() {
+        let triggers: Vec<String> = app
+            .triggers
+            .iter_multi()
+            .map(|t| {
+                let state = if app.triggers.is_group_enabled(&t.config.group) {
+                    ""
+                } else {
+                    " (off)"
+                };
+                format!(
+                    "{} until {:?} = {} [group {:?}, window {}, fired {}]{}",
+                    t.config.patterns.join(" "),
+                    t.config.until,
+                    t.config.action,
+                    t.config.group,
+                    t.config.window(),
+                    t.fires,
+                    state
+                )
+            })
+            .collect();
+        if triggers.is_empty() {
+            app.info(String::from("no multi-line trigger"));
+        }
+        triggers.into_iter().for_each(|t| app.info(t));
+        return;
+    }
+
+    let definition = multiline::parse_definition(args)
+        .and_then(|config| MultiTrigger::new(config).map_err(|e| e.to_string()));
+    match definition {
+        Ok(trigger) => {
+            let shown = format!(
+                "multi-line trigger {} = {}",
+                trigger.config.patterns.join(" "),
+                trigger.config.action
+            );
+            app.triggers.set_multi(trigger);
+            app.save_world();
+            app.info(shown);
+        }
+        Err(e) => app.info(format!("invalid multi-line trigger : {}", e)),
+    }
+}
+
+/// Splits the `-g <group>` option starting a definition
+fn group_option(definition: &str) -> (String, &str) {
+    let definition = definition.trim();
+    if definition.starts_with("-g ") {
+        let after = definition[3..].trim_start();
+        let end = after.find(char::is_whitespace).unwrap_or(after.len());
+        (String::from(&after[..end]), after[end..].trim_start())
+    } else {
+        (String::new(), definition)
+    }
+}
+
+/// Lists the gags or defines one: `[-g <group>] <pattern>`
+fn gag_command(app: &mut App, args: &str) {
+    if args.is_empty() {
+        let gags: Vec<String> = app
+            .rewrites
+            .gags()
+            .map(|g| format!("{} [group {:?}]", g.config.pattern, g.config.group))
+            .collect();
+        if gags.is_empty() {
+            app.info(String::from("no gag"));
+        }
+        gags.into_iter().for_each(|g| app.info(g));
+        return;
+    }
+
+    let (group, rest) = group_option(args);
+    let definition = trigger::whole_pattern(rest).and_then(|pattern| {
+        let config = GagConfig {
+            pattern: String::from(pattern),
+            group,
+        };
+        Gag::new(config).map_err(|e| e.to_string())
+    });
+    match definition {
+        Ok(gag) => {
+            let shown = format!("gag {}", gag.config.pattern);
+            app.rewrites.set_gag(gag);
+            app.save_world();
+            app.info(shown);
+        }
+        Err(e) => app.info(format!("invalid gag : {}", e)),
+    }
+}
+
+/// Lists the substitutions or defines one: `[-g <group>] <pattern> <replacement>`
+fn substitution_command(app: &mut App, args: &str) {
+    if args.is_empty() {
+        let substitutions: Vec<String> = app
+            .rewrites
+            .substitutions()
+            .map(|s| {
+                format!(
+                    "{} = {} [group {:?}]",
+                    s.config.pattern, s.config.replacement, s.config.group
+                )
+            })
+            .collect();
+        if substitutions.is_empty() {
+            app.info(String::from("no substitution"));
+        }
+        substitutions.into_iter().for_each(|s| app.info(s));
+        return;
+    }
+
+    let (group, rest) = group_option(args);
+    let definition = trigger::next_pattern(rest).and_then(|(pattern, replacement)| {
+        let config = SubstitutionConfig {
+            pattern: String::from(pattern),
+            replacement: String::from(replacement),
+            group,
+        };
+        Substitution::new(config).map_err(|e| e.to_string())
+    });
+    match definition {
+        Ok(substitution) => {
+            let shown = format!(
+                "substitution {} = {}",
+                substitution.config.pattern, substitution.config.replacement
+            );
+            app.rewrites.set_substitution(substitution);
+            app.save_world();
+            app.info(shown);
+        }
+        Err(e) => app.info(format!("invalid substitution : {}", e)),
+    }
+}
+
+/// Lists the highlights or defines one:
+/// `[-g <group>] <style> <pattern>` or `[-g <group>] <style> -w <word>,<word>...`
+fn highlight_command(app: &mut App, args: &str) {
+    if args.is_empty() {
+        let highlights: Vec<String> = app
+            .highlights
+            .iter()
+            .map(|h| {
+                let config = &h.config;
+                format!("{} = {} [group {:?}]", config.key(), config.style, config.group)
+            })
+            .collect();
+        if highlights.is_empty() {
+            app.info(String::from("no highlight"));
+        }
+        highlights.into_iter().for_each(|h| app.info(h));
+        return;
+    }
+
+    let (group, rest) = group_option(args);
+    let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    let (style, rest) = (&rest[..end], rest[end..].trim());
+    let mut config = HighlightConfig {
+        style: String::from(style),
+        group,
+        ..HighlightConfig::default()
+    };
+    let definition = if rest.starts_with("-w ") {
+        config.words = rest[3..]
+            .split(',')
+            .map(str::trim)
+            .filter(|word| !word.is_empty())
+            .map(String::from)
+            .collect();
+        Ok(config)
+    } else {
+        trigger::whole_pattern(rest).map(|pattern| {
+            config.pattern = String::from(pattern);
+            config
+        })
+    };
+    match definition.and_then(Highlight::new) {
+        Ok(highlight) => {
+            let config = &highlight.config;
+            let shown = format!("highlight {} = {}", config.key(), config.style);
+            app.highlights.set(highlight);
+            app.save_world();
+            app.info(shown);
+        }
+        Err(e) => app.info(format!("invalid highlight : {}", e)),
+    }
+}
+
+/// Lists the timers or manages one:
+/// `add <name> <seconds> <command>`, `warn <name> <seconds> <command>`,
+/// `pause <name>`, `resume <name>`, `resync <name>` or `remove <name>`
+fn timer_command(app: &mut App, args: &str) {
+    let now = Instant::now();
+    let words: Vec<&str> = args.splitn(4, char::is_whitespace).collect();
+
+    match words.as_slice() {
+        [] | [""] => {
+            let timers: Vec<String> = app
+                .timers
+                .iter()
+                .map(|t| {
+                    let state = if t.is_paused() { " (paused)" } else { "" };
+                    format!(
+                        "{} = {} every {}s, next in {:.1}s{}",
+                        t.config.name,
+                        t.config.command,
+                        t.config.seconds,
+                        t.remaining(now).as_secs_f64(),
+                        state
+                    )
+                })
+                .collect();
+            if timers.is_empty() {
+                app.info(String::from("no timer"));
+            }
+            timers.into_iter().for_each(|t| app.info(t));
+        }
+        ["add", name, seconds, command] => match parse_seconds(seconds, timer::check_period) {
+            Ok(seconds) => {
+                let config = TimerConfig {
+                    name: String::from(*name),
+                    seconds,
+                    command: String::from(*command),
+                    ..TimerConfig::default()
+                };
+                app.timers.add(config, now);
+                app.save_world();
+                app.info(format!("timer {} : {} every {}s", name, command, seconds));
+            }
+            Err(e) => app.info(e),
+        },
+        ["warn", name, seconds, command] => match parse_seconds(seconds, timer::check_delay) {
+            Ok(seconds) if app.timers.set_warning(name, seconds, command) => {
+                app.save_world();
+                app.info(format!("timer {} warns {}s before : {}", name, seconds, command));
+            }
+            Ok(_) => app.info(format!("no timer {}", name)),
+            Err(e) => app.info(e),
+        },
+        [action, name] => {
+            let done = match *action {
+                "pause" => app.timers.pause(name, now),
+                "resume" => app.timers.resume(name, now),
+                "resync" => app.timers.resync(name, now),
+                "remove" => {
+                    let removed = app.timers.remove(name);
+                    app.save_world();
+                    removed
+                }
+                _ => {
+                    app.info(format!("unknown timer action {}", action));
+                    return;
+                }
+            };
+            if !done {
+                app.info(format!("no timer {}", name));
+            }
+        }
+        _ => usage(app, "timer"),
+    }
+}
+
+/// Seconds given to a timer, checked to be in range
+fn parse_seconds<F>(seconds: &str, check: F) -> Result<f64, String>
+where
+    F: Fn(f64) -> Result<f64, String>,
+{
+    match seconds.parse() {
+        Ok(seconds) => check(seconds),
+        Err(_) => Err(format!("invalid seconds {}", seconds)),
+    }
+}
+
+/// Starts the connection asked by `:connect`, the commands being queued until it opens
+pub fn connect(
     app: &mut App,
-    command_sender: &mut Sender<String>,
+    command_sender: &mut Sender<CnxInput>,
+    cnx_sender: &Sender<CnxOutput>,
+) {
+    let address = match app.connection_request.take() {
+        Some(address) => address,
+        None => return,
+    };
+    let (sender, receiver) = mpsc::channel(100);
+    tokio::spawn(mudnet::connect(
+        address.clone(),
+        app.config.mud_config(),
+        receiver,
+        cnx_sender.clone(),
+    ));
+    *command_sender = sender;
+    app.connected = true;
+    app.info(format!("connecting to {}", address));
+}
+
+/// Runs the commands queued by the triggers
+pub async fn send_pending_commands(
+    app: &mut App,
+    command_sender: &mut Sender<CnxInput>,
+) -> ShouldQuit {
+    let commands: Vec<String> = app.pending_commands.drain(..).collect();
+    for command in commands {
+        if handle_string(app, command_sender, command).await {
+            return SHOULD_QUIT;
+        }
+    }
+    false
+}
+
+fn open_url(opener: &[String], url: &str) {
+    let args: Vec<String> = opener.iter().map(|arg| arg.replace("{url}", url)).collect();
+
+    match args.split_first() {
+        Some((program, args)) => {
+            if let Err(e) = Command::new(program)
+                .args(args)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+            {
+                warn!("failed to open {} : {}", url, e);
+            }
+        }
+        None => warn!("no url opener configured to open {}", url),
+    }
+}
+
+/// Sends the command of the link to the server, or opens its url
+async fn activate_link(app: &mut App, command_sender: &mut Sender<CnxInput>, link: LinkRef) {
+    let target = match app.link(link) {
+        Some(link) => link.target.clone(),
+        None => return,
+    };
+    app.selected_link = Some(link);
+
+    match target {
+        // given by the server, the command is sent back as is, never run by the client
+        LinkTarget::Command(command) => {
+            app.scrollback.push(Message::UserInput(command.clone()));
+            send_input(app, command_sender, CnxInput::Line(command)).await;
+        }
+        LinkTarget::Url(url) => open_url(&app.config.links.url_opener, &url),
+    }
+}
+
+pub async fn handle_mouse_event(
+    app: &mut App,
+    command_sender: &mut Sender<CnxInput>,
+    event: MouseEvent,
+) -> ShouldQuit {
+    match event {
+        MouseEvent::ScrollUp(x, y, _) if app.layout.area_at(x, y) == Some(AppArea::Main) => {
+            let height = app.page_height();
+            app.scrollback.scroll_up(SCROLL_ROWS, height);
+            false
+        }
+        MouseEvent::ScrollDown(x, y, _) if app.layout.area_at(x, y) == Some(AppArea::Main) => {
+            app.scrollback.scroll_down(SCROLL_ROWS);
+            false
+        }
+        MouseEvent::Down(MouseButton::Left, x, y, _) => {
+            if let Some(area) = app.layout.area_at(x, y) {
+                app.focused_area = area;
+            }
+            if let Some(link) = ui::link_at(app, x, y) {
+                activate_link(app, command_sender, link).await;
+            }
+            false
+        }
+        _ => false,
+    }
+}
+
+/// Keys typed while the search pattern is edited
+fn handle_search_key_event(app: &mut App, event: KeyEvent) {
+    let KeyEvent { code, modifiers } = event;
+
+    match code {
+        KeyCode::Char('r') if modifiers.contains(KeyModifiers::CONTROL) => {
+            app.search_step(SearchDirection::Backward)
+        }
+        KeyCode::Char('s') if modifiers.contains(KeyModifiers::CONTROL) => {
+            app.search_step(SearchDirection::Forward)
+        }
+        KeyCode::Char(c) => {
+            if let Some(search) = app.search.as_mut() {
+                search.pattern.push(c);
+            }
+            app.update_search();
+        }
+        KeyCode::Backspace => {
+            if let Some(search) = app.search.as_mut() {
+                search.pattern.pop();
+            }
+            app.update_search();
+        }
+        KeyCode::Enter => {
+            if let Some(search) = app.search.as_mut() {
+                search.editing = false;
+            }
+        }
+        _ => (),
+    }
+}
+
+async fn handle_main_key_event(
+    app: &mut App,
+    command_sender: &mut Sender<CnxInput>,
     event: KeyEvent,
 ) -> ShouldQuit {
-    let KeyEvent { code, modifiers: _ } = event;
-    app.focused_area == AppArea::Input && {
-        match code {
-            KeyCode::Enter => {
-                //let input = app.input.drain(..).collect();
-                app.input.push_str("\r\n");
-                let input = app.input.clone();
-                app.input.clear();
-                handle_string(app, command_sender, input).await
+    let KeyEvent { code, modifiers } = event;
+
+    if app.search.as_ref().map(|search| search.editing) == Some(true) {
+        handle_search_key_event(app, event);
+        return false;
+    }
+
+    let control = modifiers.contains(KeyModifiers::CONTROL);
+    let direction = app.search.as_ref().map(|search| search.direction);
+
+    match code {
+        KeyCode::Char('r') if control => {
+            app.start_search(SearchDirection::Backward);
+            false
+        }
+        KeyCode::Char('s') if control => {
+            app.start_search(SearchDirection::Forward);
+            false
+        }
+        KeyCode::Char('/') => {
+            app.start_search(SearchDirection::Backward);
+            false
+        }
+        KeyCode::Char('?') => {
+            app.start_search(SearchDirection::Forward);
+            false
+        }
+        KeyCode::Char('n') if direction.is_some() => {
+            app.search_step(direction.unwrap());
+            false
+        }
+        KeyCode::Char('N') if direction.is_some() => {
+            app.search_step(direction.unwrap().reverse());
+            false
+        }
+        KeyCode::Home => {
+            let height = app.page_height();
+            app.scrollback.scroll_to_top(height);
+            false
+        }
+        KeyCode::End => {
+            app.scrollback.scroll_to_bottom();
+            false
+        }
+        KeyCode::Tab => {
+            app.select_next_link();
+            false
+        }
+        KeyCode::BackTab => {
+            app.select_previous_link();
+            false
+        }
+        KeyCode::Enter => {
+            if let Some(link) = app.selected_link {
+                activate_link(app, command_sender, link).await;
+            }
+            false
+        }
+        _ => false,
+    }
+}
+
+/// Sends the input line, recorded in the history unless it is a password
+async fn submit_input(app: &mut App, command_sender: &mut Sender<CnxInput>) -> ShouldQuit {
+    let input = app.input.take();
+    if !app.server_echo {
+        app.history.push(&input);
+    }
+    handle_string(app, command_sender, input).await
+}
+
+/// Keys typed during a reverse search in the history
+async fn handle_history_search_key_event(
+    app: &mut App,
+    command_sender: &mut Sender<CnxInput>,
+    event: KeyEvent,
+) -> ShouldQuit {
+    let KeyEvent { code, modifiers } = event;
+    let mut search = match app.history_search.take() {
+        Some(search) => search,
+        None => return false,
+    };
+
+    match code {
+        KeyCode::Char('r') if modifiers.contains(KeyModifiers::CONTROL) => {
+            search.search_older(&app.history)
+        }
+        KeyCode::Char(c) => {
+            search.pattern.push(c);
+            search.update(&app.history);
+        }
+        KeyCode::Backspace => {
+            search.pattern.pop();
+            search.update(&app.history);
+        }
+        _ => {
+            // any other key accepts the entry found
+            if let Some(entry) = search.found.and_then(|i| app.history.get(i)) {
+                app.input.set(entry);
             }
-            KeyCode::Char(c) => {
-                app.input.push(c);
-                false
+            return code == KeyCode::Enter && submit_input(app, command_sender).await;
+        }
+    }
+
+    app.history_search = Some(search);
+    false
+}
+
+pub async fn handle_key_event(
+    app: &mut App,
+    command_sender: &mut Sender<CnxInput>,
+    event: KeyEvent,
+) -> ShouldQuit {
+    let KeyEvent { code, modifiers } = event;
+
+    if code == KeyCode::Esc {
+        // leaves the searches first
+        return app.history_search.take().is_none() && app.search.take().is_none();
+    }
+
+    if code == KeyCode::F(6) {
+        app.focused_area = match app.focused_area {
+            AppArea::Input => AppArea::Main,
+            _ => AppArea::Input,
+        };
+        return false;
+    }
+
+    // the scrollback can be paged whatever the focused area
+    match code {
+        KeyCode::PageUp => {
+            app.page_up();
+            return false;
+        }
+        KeyCode::PageDown => {
+            app.page_down();
+            return false;
+        }
+        _ => (),
+    }
+
+    if app.focused_area == AppArea::Main {
+        return handle_main_key_event(app, command_sender, event).await;
+    }
+
+    if app.focused_area == AppArea::Input && app.history_search.is_some() {
+        return handle_history_search_key_event(app, command_sender, event).await;
+    }
+
+    app.focused_area == AppArea::Input && handle_input_key_event(app, command_sender, event).await
+}
+
+async fn handle_input_key_event(
+    app: &mut App,
+    command_sender: &mut Sender<CnxInput>,
+    event: KeyEvent,
+) -> ShouldQuit {
+    let KeyEvent { code, modifiers } = event;
+    let control = modifiers.contains(KeyModifiers::CONTROL);
+    let alt = modifiers.contains(KeyModifiers::ALT);
+
+    // only Up and Down go on recalling the history
+    if code != KeyCode::Up && code != KeyCode::Down {
+        app.history.reset();
+    }
+    if code != KeyCode::Tab && code != KeyCode::BackTab {
+        app.completion = None;
+    }
+
+    let input = &mut app.input;
+    match code {
+        KeyCode::Enter => return submit_input(app, command_sender).await,
+        KeyCode::Up => {
+            if let Some(line) = app.history.previous(input.text()).map(String::from) {
+                input.set(&line);
             }
-            KeyCode::Backspace => {
-                app.input.pop();
-                false
+        }
+        KeyCode::Down => {
+            if let Some(line) = app.history.next().map(String::from) {
+                input.set(&line);
             }
-            _ => false,
         }
+        KeyCode::Tab => app.complete(true),
+        KeyCode::BackTab => app.complete(false),
+        KeyCode::Char('r') if control => app.history_search = Some(HistorySearch::new()),
+        KeyCode::Char('w') if control => input.kill_word(),
+        KeyCode::Char('u') if control => input.kill_to_start(),
+        KeyCode::Char('k') if control => input.kill_to_end(),
+        KeyCode::Char('y') if control => input.yank(),
+        KeyCode::Char('a') if control => input.home(),
+        KeyCode::Char('e') if control => input.end(),
+        KeyCode::Char('b') if alt => input.word_left(),
+        KeyCode::Char('f') if alt => input.word_right(),
+        KeyCode::Char(c) if !control && !alt => input.insert(c),
+        KeyCode::Left if control => input.word_left(),
+        KeyCode::Right if control => input.word_right(),
+        KeyCode::Left => input.left(),
+        KeyCode::Right => input.right(),
+        KeyCode::Home => input.home(),
+        KeyCode::End => input.end(),
+        KeyCode::Backspace => input.backspace(),
+        KeyCode::Delete => input.delete(),
+        _ => (),
     }
+    false
 }
@@ -0,0 +1,222 @@
+use mudnet::mud;
+
+/// A command of the client, typed after the prefix
+pub struct CommandInfo {
+    pub name: &'static str,
+    /// `<required>` and `[optional]` arguments
+    pub args: &'static str,
+    pub help: &'static str,
+}
+
+const fn command(name: &'static str, args: &'static str, help: &'static str) -> CommandInfo {
+    CommandInfo { name, args, help }
+}
+
+/// The commands of the client, by name
+pub const COMMANDS: [CommandInfo; 27] = [
+    command("alias", "[<pattern> [<body>]]", "lists, shows or defines the aliases"),
+    command("call", "<function> [<argument>...]", "calls a function of the scripts"),
+    command("connect", "[<host> <port>]", "connects to the world, or to another server"),
+    command("delay", "<seconds> <command>", "runs a command once after a delay"),
+    command("disconnect", "", "closes the connection"),
+    command("gag", "[[-g <group>] <pattern>]", "lists the gags or hides the matching lines"),
+    command("gmcp", "<Package.Message> [<json>]", "sends a GMCP message"),
+    command(
+        "group",
+        "<group> on|off",
+        "turns the triggers, gags and highlights of a group on or off",
+    ),
+    command("help", "[<command>]", "lists the commands or describes one"),
+    command(
+        "highlight",
+        "[[-g <group>] <style> <pattern>|-w <word>,<word>...]",
+        "lists the highlights or colors the matching text",
+    ),
+    command("msdp", "<variable> <value>", "sends an MSDP variable, e.g. SEND HEALTH"),
+    command(
+        "mtrigger",
+        "[[-w <lines>] [-until <pattern>] [-g <group>] <pattern>... => <action>]",
+        "lists the multi-line triggers or defines one",
+    ),
+    command("options", "", "shows the telnet options negotiated"),
+    command("q", "", "quits"),
+    command("script", "[reload]", "lists the scripts or runs them all again"),
+    command("set", "[<variable> [<value>]]", "lists, shows or sets the variables"),
+    command(
+        "subst",
+        "[[-g <group>] <pattern> <replacement>]",
+        "lists the substitutions or rewrites the matching text",
+    ),
+    command(
+        "telnet",
+        "do|dont|will|wont <option> | sb <option> <data>",
+        "sends a telnet negotiation, the option being a name or a code",
+    ),
+    command("tgroup", "<group> on|off", "same as group"),
+    command(
+        "timer",
+        "[add|warn <name> <seconds> <command> | pause|resume|resync|remove <name>]",
+        "lists the timers or manages one",
+    ),
+    command(
+        "trigger",
+        "[[-p <priority>] [-once] [-prompt] [-g <group>] <pattern> <action>]",
+        "lists the triggers or defines one",
+    ),
+    command("unalias", "<pattern>", "removes an alias"),
+    command("ungag", "<pattern>", "removes a gag"),
+    command("unhighlight", "<pattern>|<word>,<word>...", "removes a highlight"),
+    command("unset", "<variable>", "removes a variable"),
+    command("unsubst", "<pattern>", "removes a substitution"),
+    command("untrigger", "<pattern>", "removes a trigger"),
+];
+
+/// Telnet options known by name besides the MUD ones
+const TELNET_OPTIONS: [(&'static str, u8); 8] = [
+    ("BINARY", 0),
+    ("ECHO", 1),
+    ("SGA", 3),
+    ("TTYPE", 24),
+    ("EOR", 25),
+    ("NAWS", 31),
+    ("NEW-ENVIRON", 39),
+    ("CHARSET", 42),
+];
+
+pub fn find(name: &str) -> Option<&'static CommandInfo> {
+    COMMANDS.iter().find(|c| c.name == name)
+}
+
+/// Whether a command is run by the client: it starts with the prefix, unless the prefix is doubled
+pub fn is_client_command(command: &str, prefix: &str) -> bool {
+    let command = command.trim_start();
+    !prefix.is_empty()
+        && command.starts_with(prefix)
+        && !command[prefix.len()..].starts_with(prefix)
+}
+
+/// A command starting with the doubled prefix is sent with a single one, e.g. `::)`
+pub fn unescape<'a>(command: &'a str, prefix: &str) -> &'a str {
+    let trimmed = command.trim_start();
+    let doubled = !prefix.is_empty()
+        && trimmed.starts_with(prefix)
+        && trimmed[prefix.len()..].starts_with(prefix);
+    if doubled {
+        &trimmed[prefix.len()..]
+    } else {
+        command
+    }
+}
+
+/// Splits a client command into its name, without the prefix, and its arguments
+pub fn parse<'a>(command: &'a str, prefix: &str) -> (&'a str, &'a str) {
+    let command = command.trim();
+    let command = command.strip_prefix(prefix).unwrap_or(command);
+    match command.find(char::is_whitespace) {
+        Some(i) => (&command[..i], command[i..].trim()),
+        None => (command, ""),
+    }
+}
+
+/// Splits the arguments on the whitespace, the `"double"` or `'single'` quotes keeping it.
+/// A `\` escapes the next character, except between single quotes.
+pub fn tokenize(args: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    let mut chars = args.chars();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') | (None, '\\') => {
+                word.push(chars.next().unwrap_or('\\'));
+                in_word = true;
+            }
+            (Some(_), c) => word.push(c),
+            (None, '"') | (None, '\'') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if quote.is_some() {
+        return Err(String::from("unclosed quote"));
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+/// Code of a telnet option given by its code or its name, e.g. `201` or `gmcp`
+pub fn option_code(name: &str) -> Option<u8> {
+    if let Ok(code) = name.parse() {
+        return Some(code);
+    }
+    let name = name.to_ascii_uppercase();
+    TELNET_OPTIONS
+        .iter()
+        .find(|(option, _)| *option == name)
+        .map(|(_, code)| *code)
+        .or_else(|| {
+            (0..=255).find(|code| mud::str_of_mud_option(*code).ok() == Some(name.as_str()))
+        })
+}
+
+/// Name of a telnet option, its code when it is unknown
+pub fn option_name(code: u8) -> String {
+    match TELNET_OPTIONS.iter().find(|(_, c)| *c == code) {
+        Some((name, _)) => String::from(*name),
+        None => mud::str_of_mud_option(code)
+            .map(String::from)
+            .unwrap_or_else(|_| code.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_with_a_prefix() {
+        assert!(is_client_command(":alias k kill %1", ":"));
+        assert!(!is_client_command("::)", ":"));
+        assert!(!is_client_command("say hi", ":"));
+        assert!(is_client_command("/set a b", "/"));
+        assert_eq!(unescape("::)", ":"), ":)");
+        assert_eq!(unescape("smile", ":"), "smile");
+        assert_eq!(parse(":set target  goblin ", ":"), ("set", "target  goblin"));
+        assert_eq!(parse("//help", "//"), ("help", ""));
+    }
+
+    #[test]
+    fn tokenizes_with_quotes() {
+        assert_eq!(
+            tokenize(r#"heal "big bob" 'a \ b' c\ d """#).unwrap(),
+            vec!["heal", "big bob", "a \\ b", "c d", ""]
+        );
+        assert!(tokenize("say \"hi").is_err());
+    }
+
+    #[test]
+    fn names_the_options() {
+        assert_eq!(option_code("gmcp"), Some(201));
+        assert_eq!(option_code("TTYPE"), Some(24));
+        assert_eq!(option_code("86"), Some(86));
+        assert_eq!(option_code("mccp4"), None);
+        assert_eq!(option_name(69), "MSDP");
+        assert_eq!(option_name(250), "250");
+    }
+}
@@ -0,0 +1,98 @@
+const MIN_WORD_LEN: usize = 3;
+
+/// Words completing the given prefix, ignoring the case, in the order of the sources and without duplicates
+pub fn candidates<'a, I>(prefix: &str, words: I) -> Vec<String>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let lowercase = prefix.to_lowercase();
+    let mut candidates: Vec<String> = Vec::new();
+
+    for word in words {
+        if word != prefix
+            && word.to_lowercase().starts_with(&lowercase)
+            && !candidates.iter().any(|c| c == word)
+        {
+            candidates.push(String::from(word));
+        }
+    }
+    candidates
+}
+
+/// Words of a text worth completing
+pub fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '-' || c == '\''))
+        .map(|word| word.trim_matches(|c| c == '-' || c == '\''))
+        .filter(|word| word.chars().count() >= MIN_WORD_LEN)
+}
+
+/// Completion of the word before the cursor, cycling through the candidates
+pub struct Completion {
+    /// byte offset of the completed word in the input
+    start: usize,
+    /// the word typed, given back after the last candidate
+    prefix: String,
+    candidates: Vec<String>,
+    /// `candidates.len()` stands for the prefix
+    index: usize,
+}
+
+impl Completion {
+    pub fn new(start: usize, prefix: &str, candidates: Vec<String>) -> Completion {
+        let index = candidates.len();
+        Completion {
+            start,
+            prefix: String::from(prefix),
+            candidates,
+            index,
+        }
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn current(&self) -> &str {
+        self.candidates
+            .get(self.index)
+            .map_or(self.prefix.as_str(), String::as_str)
+    }
+
+    pub fn next(&mut self) -> &str {
+        self.index = (self.index + 1) % (self.candidates.len() + 1);
+        self.current()
+    }
+
+    pub fn previous(&mut self) -> &str {
+        let len = self.candidates.len() + 1;
+        self.index = (self.index + len - 1) % len;
+        self.current()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_candidates() {
+        let text = "A goblin shaman arrives. The goblin-guard says 'Gobble!'";
+        let sources = [":gag", ":go"];
+        let candidates = candidates(
+            "gob",
+            sources.iter().cloned().chain(words(text)).chain(words(text)),
+        );
+        assert_eq!(candidates, vec!["goblin", "goblin-guard", "Gobble"]);
+    }
+
+    #[test]
+    fn cycles() {
+        let candidates = vec![String::from("goblin"), String::from("Gobble")];
+        let mut completion = Completion::new(4, "gob", candidates);
+        assert_eq!(completion.next(), "goblin");
+        assert_eq!(completion.next(), "Gobble");
+        assert_eq!(completion.next(), "gob");
+        assert_eq!(completion.previous(), "Gobble");
+        assert_eq!(completion.start(), 4);
+    }
+}
@@ -23,6 +23,7 @@ pub enum Event<I, N> {
 pub struct Events {
     rx: Receiver<Event<CEvent, CnxOutput>>,
     input_handle: task::JoinHandle<()>,
+    tick_handle: task::JoinHandle<()>,
     ignore_exit_key: Arc<AtomicBool>,
 }
 
@@ -49,6 +50,19 @@ impl Events {
     pub fn with_config(config: Config, mut network: Receiver<CnxOutput>) -> Events {
         let (mut tx, mut rx) = mpsc::channel(100);
         let ignore_exit_key = Arc::new(AtomicBool::new(false));
+        // the ticks drive the timers
+        let tick_handle = {
+            let mut tx = tx.clone();
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(config.tick_rate);
+                loop {
+                    interval.tick().await;
+                    if let Err(_) = tx.send(Event::Tick).await {
+                        return;
+                    }
+                }
+            })
+        };
         let input_handle = {
             let ignore_exit_key = ignore_exit_key.clone();
             tokio::spawn(async move {
@@ -93,6 +107,7 @@ impl Events {
             rx,
             ignore_exit_key,
             input_handle,
+            tick_handle,
         }
     }
 
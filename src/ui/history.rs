@@ -0,0 +1,209 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Commands sent, the oldest ones being dropped once the capacity is reached
+pub struct History {
+    entries: VecDeque<String>,
+    capacity: usize,
+    /// file the history is loaded from and saved to
+    path: Option<PathBuf>,
+    /// index of the recalled entry, `None` while typing a new line
+    position: Option<usize>,
+    /// line typed before the recall, only the entries starting with it are recalled
+    prefix: String,
+}
+
+/// Reverse incremental search in the history
+pub struct HistorySearch {
+    pub pattern: String,
+    /// index of the entry found
+    pub found: Option<usize>,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> History {
+        History {
+            entries: VecDeque::new(),
+            capacity: capacity.max(1),
+            path: None,
+            position: None,
+            prefix: String::new(),
+        }
+    }
+
+    /// Reads the history saved in the given file, if any
+    pub fn load(path: &Path, capacity: usize) -> io::Result<History> {
+        let mut history = History::new(capacity);
+        history.path = Some(PathBuf::from(path));
+
+        match fs::read_to_string(path) {
+            Ok(content) => content.lines().for_each(|line| history.push(line)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => return Err(e),
+        }
+        Ok(history)
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        if let Some(path) = &self.path {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let mut content = String::new();
+            for entry in self.entries.iter() {
+                content.push_str(entry);
+                content.push('\n');
+            }
+            fs::write(path, content)?;
+        }
+        Ok(())
+    }
+
+    /// Records a line, unless it is empty or repeats the last one
+    pub fn push(&mut self, line: &str) {
+        self.reset();
+        if line.trim().is_empty() || self.entries.back().map(String::as_str) == Some(line) {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(String::from(line));
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.entries.get(index).map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Stops recalling, the next recall starts from the most recent entry
+    pub fn reset(&mut self) {
+        self.position = None;
+    }
+
+    /// Recalls the entry preceding the recalled one that starts with the line typed before the recall
+    pub fn previous(&mut self, current: &str) -> Option<&str> {
+        let end = match self.position {
+            Some(position) => position,
+            None => {
+                self.prefix = String::from(current);
+                self.entries.len()
+            }
+        };
+
+        let prefix = &self.prefix;
+        let found = (0..end)
+            .rev()
+            .find(|i| self.entries[*i].starts_with(prefix.as_str()))?;
+        self.position = Some(found);
+        self.get(found)
+    }
+
+    /// Recalls the following entry, or gives back the line typed before the recall
+    pub fn next(&mut self) -> Option<&str> {
+        let start = self.position? + 1;
+
+        let prefix = &self.prefix;
+        match (start..self.entries.len()).find(|i| self.entries[*i].starts_with(prefix.as_str())) {
+            Some(found) => {
+                self.position = Some(found);
+                self.get(found)
+            }
+            None => {
+                self.position = None;
+                Some(self.prefix.as_str())
+            }
+        }
+    }
+
+    /// Most recent entry before `before` containing the pattern
+    pub fn search(&self, pattern: &str, before: usize) -> Option<usize> {
+        (0..before.min(self.entries.len()))
+            .rev()
+            .find(|i| self.entries[*i].contains(pattern))
+    }
+}
+
+impl HistorySearch {
+    pub fn new() -> HistorySearch {
+        HistorySearch {
+            pattern: String::new(),
+            found: None,
+        }
+    }
+
+    /// Searches the pattern again from the most recent entry
+    pub fn update(&mut self, history: &History) {
+        self.found = history.search(&self.pattern, history.len());
+    }
+
+    /// Searches an older entry containing the pattern, stays on the found one if there is none
+    pub fn search_older(&mut self, history: &History) {
+        let before = self.found.unwrap_or_else(|| history.len());
+        if let Some(found) = history.search(&self.pattern, before) {
+            self.found = Some(found);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history() -> History {
+        let mut history = History::new(10);
+        for line in ["cast fireball", "north", "north", "cast heal", "look", ""].iter() {
+            history.push(line);
+        }
+        history
+    }
+
+    #[test]
+    fn recalls_with_prefix() {
+        let mut history = history();
+        assert_eq!(history.len(), 4);
+
+        assert_eq!(history.previous("ca"), Some("cast heal"));
+        assert_eq!(history.previous("cast heal"), Some("cast fireball"));
+        assert_eq!(history.previous("cast fireball"), None);
+        assert_eq!(history.next(), Some("cast heal"));
+        assert_eq!(history.next(), Some("ca"));
+        assert_eq!(history.next(), None);
+
+        assert_eq!(history.previous(""), Some("look"));
+        assert_eq!(history.previous("look"), Some("cast heal"));
+    }
+
+    #[test]
+    fn reverse_search() {
+        let history = history();
+        let mut search = HistorySearch::new();
+
+        search.pattern.push_str("ca");
+        search.update(&history);
+        assert_eq!(search.found, Some(2));
+        search.search_older(&history);
+        assert_eq!(search.found, Some(0));
+        search.search_older(&history);
+        assert_eq!(search.found, Some(0));
+
+        search.pattern.push_str("z");
+        search.update(&history);
+        assert_eq!(search.found, None);
+    }
+
+    #[test]
+    fn bounded() {
+        let mut history = History::new(2);
+        history.push("n");
+        history.push("s");
+        history.push("e");
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.get(0), Some("s"));
+    }
+}
@@ -0,0 +1,296 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Text of the Input area with its cursor, moved and edited by graphemes
+#[derive(Debug, Clone, Default)]
+pub struct LineEditor {
+    text: String,
+    /// byte offset of the cursor, always on a grapheme boundary
+    cursor: usize,
+    /// text removed by the last kill command
+    yanked: String,
+}
+
+fn is_space(g: &str) -> bool {
+    g.chars().all(char::is_whitespace)
+}
+
+impl LineEditor {
+    pub fn new() -> LineEditor {
+        LineEditor::default()
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// Replaces the text, the cursor being moved at its end
+    pub fn set(&mut self, text: &str) {
+        self.text = String::from(text);
+        self.cursor = self.text.len();
+    }
+
+    /// Takes the text out, leaving the editor empty
+    pub fn take(&mut self) -> String {
+        self.cursor = 0;
+        std::mem::replace(&mut self.text, String::new())
+    }
+
+    pub fn insert(&mut self, c: char) {
+        self.text.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+    }
+
+    pub fn insert_str(&mut self, s: &str) {
+        self.text.insert_str(self.cursor, s);
+        self.cursor += s.len();
+    }
+
+    fn previous_boundary(&self, from: usize) -> usize {
+        self.text[..from]
+            .grapheme_indices(true)
+            .next_back()
+            .map_or(0, |(i, _)| i)
+    }
+
+    fn next_boundary(&self, from: usize) -> usize {
+        self.text[from..]
+            .graphemes(true)
+            .next()
+            .map_or(from, |g| from + g.len())
+    }
+
+    /// Start of the word before the given offset, skipping the spaces preceding it
+    fn word_start(&self, from: usize) -> usize {
+        let mut graphemes = self.text[..from].grapheme_indices(true).rev().peekable();
+        while graphemes.peek().map_or(false, |(_, g)| is_space(g)) {
+            graphemes.next();
+        }
+        let mut start = graphemes.peek().map_or(0, |(i, _)| *i);
+        for (i, g) in graphemes {
+            if is_space(g) {
+                break;
+            }
+            start = i;
+        }
+        start
+    }
+
+    /// End of the word after the given offset, skipping the spaces preceding it
+    fn word_end(&self, from: usize) -> usize {
+        let mut end = from;
+        let mut in_word = false;
+        for (i, g) in self.text[from..].grapheme_indices(true) {
+            if is_space(g) && in_word {
+                break;
+            }
+            in_word = in_word || !is_space(g);
+            end = from + i + g.len();
+        }
+        end
+    }
+
+    /// The word before the cursor, with its byte offset
+    pub fn current_word(&self) -> (usize, &str) {
+        let start = self.text[..self.cursor]
+            .grapheme_indices(true)
+            .rev()
+            .take_while(|(_, g)| !is_space(g))
+            .last()
+            .map_or(self.cursor, |(i, _)| i);
+        (start, &self.text[start..self.cursor])
+    }
+
+    /// Replaces the text between `start` and the cursor
+    pub fn replace_before_cursor(&mut self, start: usize, s: &str) {
+        self.text.replace_range(start..self.cursor, s);
+        self.cursor = start + s.len();
+    }
+
+    pub fn left(&mut self) {
+        self.cursor = self.previous_boundary(self.cursor);
+    }
+
+    pub fn right(&mut self) {
+        self.cursor = self.next_boundary(self.cursor);
+    }
+
+    pub fn home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn end(&mut self) {
+        self.cursor = self.text.len();
+    }
+
+    pub fn word_left(&mut self) {
+        self.cursor = self.word_start(self.cursor);
+    }
+
+    pub fn word_right(&mut self) {
+        self.cursor = self.word_end(self.cursor);
+    }
+
+    pub fn backspace(&mut self) {
+        let start = self.previous_boundary(self.cursor);
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    pub fn delete(&mut self) {
+        let end = self.next_boundary(self.cursor);
+        self.text.replace_range(self.cursor..end, "");
+    }
+
+    fn kill(&mut self, start: usize, end: usize) {
+        if start < end {
+            self.yanked = self.text[start..end].to_string();
+            self.text.replace_range(start..end, "");
+            self.cursor = start;
+        }
+    }
+
+    /// Ctrl+W: removes the word before the cursor
+    pub fn kill_word(&mut self) {
+        self.kill(self.word_start(self.cursor), self.cursor);
+    }
+
+    /// Ctrl+U: removes the text before the cursor
+    pub fn kill_to_start(&mut self) {
+        self.kill(0, self.cursor);
+    }
+
+    /// Ctrl+K: removes the text after the cursor
+    pub fn kill_to_end(&mut self) {
+        self.kill(self.cursor, self.text.len());
+    }
+
+    /// Ctrl+Y: inserts the text removed by the last kill
+    pub fn yank(&mut self) {
+        let yanked = self.yanked.clone();
+        self.insert_str(&yanked);
+    }
+
+    /// Part of the text fitting in `width` columns, scrolled to keep the cursor visible,
+    /// and the byte offset of the cursor in it. A masked text shows a `*` per grapheme.
+    pub fn view(&self, width: usize, mask: bool) -> (String, usize) {
+        let width = width.max(1);
+        let shown = |s: &str| -> String {
+            if mask {
+                "*".repeat(s.graphemes(true).count())
+            } else {
+                String::from(s)
+            }
+        };
+        let (before, after) = (shown(&self.text[..self.cursor]), shown(&self.text[self.cursor..]));
+
+        // the cursor stays on the last column when the text before it is too wide
+        let mut skipped = 0;
+        let mut start = 0;
+        let overflow = (before.width() + 1).saturating_sub(width);
+        for (i, g) in before.grapheme_indices(true) {
+            if skipped >= overflow {
+                break;
+            }
+            skipped += g.width();
+            start = i + g.len();
+        }
+        let before = &before[start..];
+
+        let mut view = String::from(before);
+        let mut used = before.width();
+        for g in after.graphemes(true) {
+            if used + g.width() > width {
+                break;
+            }
+            used += g.width();
+            view.push_str(g);
+        }
+        (view, before.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edits_at_cursor() {
+        let mut editor = LineEditor::new();
+        editor.set("cast heal");
+        editor.word_left();
+        assert_eq!(editor.cursor(), 5);
+        editor.insert_str("big ");
+        assert_eq!(editor.text(), "cast big heal");
+
+        editor.home();
+        editor.delete();
+        editor.word_right();
+        assert_eq!(editor.cursor(), 3);
+        editor.backspace();
+        assert_eq!(editor.text(), "as big heal");
+    }
+
+    #[test]
+    fn kills_and_yanks() {
+        let mut editor = LineEditor::new();
+        editor.set("tell bob  hello there");
+        editor.kill_word();
+        assert_eq!(editor.text(), "tell bob  hello ");
+        editor.kill_word();
+        assert_eq!(editor.text(), "tell bob  ");
+        editor.yank();
+        assert_eq!(editor.text(), "tell bob  hello ");
+
+        editor.word_left();
+        editor.word_left();
+        editor.kill_to_end();
+        assert_eq!(editor.text(), "tell ");
+        editor.kill_to_start();
+        assert!(editor.is_empty());
+        editor.yank();
+        assert_eq!(editor.text(), "tell ");
+    }
+
+    #[test]
+    fn replaces_current_word() {
+        let mut editor = LineEditor::new();
+        editor.set("kill gob");
+        assert_eq!(editor.current_word(), (5, "gob"));
+        editor.replace_before_cursor(5, "goblin");
+        assert_eq!(editor.text(), "kill goblin");
+        editor.insert(' ');
+        assert_eq!(editor.current_word(), (12, ""));
+    }
+
+    #[test]
+    fn moves_by_graphemes() {
+        let mut editor = LineEditor::new();
+        editor.set("ne\u{301}e");
+        editor.left();
+        editor.left();
+        assert_eq!(editor.cursor(), 1);
+        editor.delete();
+        assert_eq!(editor.text(), "ne");
+    }
+
+    #[test]
+    fn scrolls_to_cursor() {
+        let mut editor = LineEditor::new();
+        editor.set("abcdefgh");
+        assert_eq!(editor.view(5, false), (String::from("efgh"), 4));
+        editor.home();
+        assert_eq!(editor.view(5, false), (String::from("abcde"), 0));
+        editor.set("日本語");
+        assert_eq!(editor.view(4, true), (String::from("***"), 3));
+        assert_eq!(editor.view(4, false), (String::from("語"), 3));
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::VecDeque;
+use std::ops::Range;
+
+/// Lines received from the server, the oldest ones being dropped once the capacity is reached.
+///
+/// Lines are addressed by an absolute index which does not change when older lines are dropped,
+/// `end()` being the index of the line still being received.
+pub struct Scrollback<T> {
+    lines: VecDeque<T>,
+    capacity: usize,
+    /// absolute index of the oldest line kept
+    first: usize,
+    /// number of lines scrolled up from the bottom, 0 when following the output
+    offset: usize,
+}
+
+impl<T> Scrollback<T> {
+    pub fn new(capacity: usize) -> Scrollback<T> {
+        Scrollback {
+            lines: VecDeque::new(),
+            capacity: capacity.max(1),
+            first: 0,
+            offset: 0,
+        }
+    }
+
+    pub fn push(&mut self, line: T) {
+        if self.lines.len() == self.capacity {
+            self.lines.pop_front();
+            self.first += 1;
+        }
+        self.lines.push_back(line);
+
+        // keep the view still while scrolled back
+        if self.offset > 0 {
+            self.offset = (self.offset + 1).min(self.lines.len());
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        index
+            .checked_sub(self.first)
+            .and_then(|i| self.lines.get(i))
+    }
+
+    pub fn first(&self) -> usize {
+        self.first
+    }
+
+    /// Index following the last line, i.e. the index of the pending line
+    pub fn end(&self) -> usize {
+        self.first + self.lines.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// Lines with their index
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &T)> {
+        let first = self.first;
+        self.lines
+            .iter()
+            .enumerate()
+            .map(move |(i, line)| (first + i, line))
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn is_following(&self) -> bool {
+        self.offset == 0
+    }
+
+    // the pending line is a row as well
+    fn max_offset(&self, height: usize) -> usize {
+        (self.lines.len() + 1).saturating_sub(height.max(1))
+    }
+
+    pub fn scroll_up(&mut self, rows: usize, height: usize) {
+        self.offset = (self.offset + rows).min(self.max_offset(height));
+    }
+
+    pub fn scroll_down(&mut self, rows: usize) {
+        self.offset = self.offset.saturating_sub(rows);
+    }
+
+    pub fn scroll_to_top(&mut self, height: usize) {
+        self.offset = self.max_offset(height);
+    }
+
+    pub fn scroll_to_bottom(&mut self) {
+        self.offset = 0;
+    }
+
+    /// Scrolls so that the line at the given index is visible
+    pub fn scroll_to(&mut self, index: usize, height: usize) {
+        if self.visible(height).contains(&index) {
+            return;
+        }
+        let stop = (index + height / 2 + 1).min(self.end() + 1);
+        self.offset = (self.end() + 1 - stop).min(self.max_offset(height));
+    }
+
+    /// Index following the last line of the view
+    pub fn bottom(&self) -> usize {
+        self.end() + 1 - self.offset
+    }
+
+    /// Indexes of the lines shown in a view of the given height, one line per row
+    pub fn visible(&self, height: usize) -> Range<usize> {
+        let stop = self.bottom();
+        stop.saturating_sub(height).max(self.first)..stop
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_oldest_lines() {
+        let mut scrollback = Scrollback::new(3);
+        for i in 0..5 {
+            scrollback.push(i);
+        }
+        assert_eq!(scrollback.len(), 3);
+        assert_eq!(scrollback.first(), 2);
+        assert_eq!(scrollback.end(), 5);
+        assert_eq!(scrollback.get(1), None);
+        assert_eq!(scrollback.get(2), Some(&2));
+        assert_eq!(scrollback.get(4), Some(&4));
+        assert_eq!(scrollback.get(5), None);
+    }
+
+    #[test]
+    fn follows_output_only_at_bottom() {
+        let mut scrollback = Scrollback::new(100);
+        for i in 0..10 {
+            scrollback.push(i);
+        }
+        assert_eq!(scrollback.visible(4), 7..11);
+
+        scrollback.scroll_up(3, 4);
+        assert_eq!(scrollback.visible(4), 4..8);
+        scrollback.push(10);
+        assert_eq!(scrollback.visible(4), 4..8);
+        assert_eq!(scrollback.bottom(), 8);
+
+        scrollback.scroll_to_top(4);
+        assert_eq!(scrollback.visible(4), 0..4);
+        scrollback.scroll_up(4, 4);
+        assert_eq!(scrollback.visible(4), 0..4);
+
+        scrollback.scroll_to(8, 4);
+        assert_eq!(scrollback.visible(4), 7..11);
+
+        scrollback.scroll_to_bottom();
+        scrollback.push(11);
+        assert!(scrollback.is_following());
+        assert_eq!(scrollback.visible(4), 9..13);
+    }
+}
@@ -0,0 +1,188 @@
+use regex::Regex;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SearchDirection {
+    /// towards the older lines
+    Backward,
+    /// towards the newer lines
+    Forward,
+}
+
+impl SearchDirection {
+    pub fn reverse(self) -> SearchDirection {
+        match self {
+            SearchDirection::Backward => SearchDirection::Forward,
+            SearchDirection::Forward => SearchDirection::Backward,
+        }
+    }
+}
+
+/// A match: index of the line in the scrollback and byte range in the line text
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct SearchMatch {
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Incremental regex search in the scrollback
+pub struct Search {
+    pub pattern: String,
+    pub direction: SearchDirection,
+    /// true while the pattern is being typed
+    pub editing: bool,
+    /// false when the pattern is not a valid regex
+    pub valid: bool,
+    /// line the search started from
+    origin: usize,
+    /// ordered by position
+    matches: Vec<SearchMatch>,
+    current: Option<usize>,
+}
+
+impl Search {
+    pub fn new(direction: SearchDirection, origin: usize) -> Search {
+        Search {
+            pattern: String::new(),
+            direction,
+            editing: true,
+            valid: true,
+            origin,
+            matches: Vec::new(),
+            current: None,
+        }
+    }
+
+    /// Searches the pattern again in the given lines, then selects the first match from the origin
+    pub fn update<'a, I>(&mut self, lines: I)
+    where
+        I: Iterator<Item = (usize, &'a str)>,
+    {
+        self.matches.clear();
+        self.current = None;
+
+        if self.pattern.is_empty() {
+            self.valid = true;
+            return;
+        }
+
+        let regex = match Regex::new(&self.pattern) {
+            Ok(regex) => regex,
+            Err(_) => {
+                self.valid = false;
+                return;
+            }
+        };
+        self.valid = true;
+
+        for (line, text) in lines {
+            for m in regex.find_iter(text).filter(|m| !m.as_str().is_empty()) {
+                self.matches.push(SearchMatch {
+                    line,
+                    start: m.start(),
+                    end: m.end(),
+                });
+            }
+        }
+
+        let origin = self.origin;
+        self.current = match self.direction {
+            SearchDirection::Backward => self.matches.iter().rposition(|m| m.line <= origin),
+            SearchDirection::Forward => self.matches.iter().position(|m| m.line >= origin),
+        }
+        .or_else(|| self.wrapped());
+    }
+
+    // first match when going round the scrollback
+    fn wrapped(&self) -> Option<usize> {
+        if self.matches.is_empty() {
+            None
+        } else if self.direction == SearchDirection::Backward {
+            Some(self.matches.len() - 1)
+        } else {
+            Some(0)
+        }
+    }
+
+    /// Moves to the next match in the given direction, going round the scrollback
+    pub fn step(&mut self, direction: SearchDirection) {
+        let len = self.matches.len();
+        self.current = match (self.current, direction) {
+            (_, _) if len == 0 => None,
+            (Some(i), SearchDirection::Backward) => Some((i + len - 1) % len),
+            (Some(i), SearchDirection::Forward) => Some((i + 1) % len),
+            (None, _) => self.wrapped(),
+        };
+        if let Some(m) = self.current() {
+            self.origin = m.line;
+        }
+    }
+
+    pub fn current(&self) -> Option<SearchMatch> {
+        self.current.map(|i| self.matches[i])
+    }
+
+    /// Position of the current match, from 1, and number of matches
+    pub fn position(&self) -> (usize, usize) {
+        (self.current.map(|i| i + 1).unwrap_or(0), self.matches.len())
+    }
+
+    /// Matches of a line, with whether they are the current one
+    pub fn line_matches(&self, line: usize) -> Vec<(SearchMatch, bool)> {
+        let current = self.current();
+        self.matches
+            .iter()
+            .filter(|m| m.line == line)
+            .map(|m| (*m, Some(*m) == current))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINES: [&str; 4] = [
+        "Gandalf tells you 'hello'",
+        "A rat arrives.",
+        "Bilbo tells you 'hi' and tells you 'bye'",
+        "You are hungry.",
+    ];
+
+    fn lines() -> impl Iterator<Item = (usize, &'static str)> {
+        LINES.iter().enumerate().map(|(i, l)| (i + 10, *l))
+    }
+
+    #[test]
+    fn incremental_backward_search() {
+        let mut search = Search::new(SearchDirection::Backward, 14);
+
+        search.pattern.push_str("tells");
+        search.update(lines());
+        assert_eq!(search.position(), (3, 3));
+        assert_eq!(search.current().map(|m| (m.line, m.start)), Some((12, 25)));
+
+        search.pattern.push_str(" you '(h|b)");
+        search.update(lines());
+        assert_eq!(search.position(), (3, 3));
+
+        search.step(SearchDirection::Backward);
+        assert_eq!(search.current().map(|m| (m.line, m.start)), Some((12, 6)));
+        search.step(SearchDirection::Backward);
+        search.step(SearchDirection::Backward);
+        assert_eq!(search.position(), (3, 3));
+        search.step(SearchDirection::Forward);
+        assert_eq!(search.position(), (1, 3));
+        assert_eq!(search.line_matches(10).len(), 1);
+        assert!(search.line_matches(10)[0].1);
+    }
+
+    #[test]
+    fn invalid_pattern() {
+        let mut search = Search::new(SearchDirection::Forward, 10);
+        search.pattern.push_str("tells (");
+        search.update(lines());
+        assert!(!search.valid);
+        assert_eq!(search.position(), (0, 0));
+    }
+}
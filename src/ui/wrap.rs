@@ -0,0 +1,169 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::ansi::StyledLine;
+
+/// Part of a span displayed on a row: index of the span and byte range in its text
+#[derive(Debug, Clone, PartialEq)]
+pub struct Piece {
+    pub span: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A screen row of a wrapped line
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Row {
+    /// columns left blank before the text, the hanging indent of the continuation rows
+    pub indent: usize,
+    pub pieces: Vec<Piece>,
+}
+
+struct Grapheme {
+    span: usize,
+    start: usize,
+    end: usize,
+    width: usize,
+    space: bool,
+}
+
+fn graphemes(line: &StyledLine) -> Vec<Grapheme> {
+    let mut graphemes = Vec::new();
+    for (i, span) in line.spans.iter().enumerate() {
+        for (start, g) in span.text.grapheme_indices(true) {
+            graphemes.push(Grapheme {
+                span: i,
+                start,
+                end: start + g.len(),
+                width: g.width(),
+                space: g.chars().all(char::is_whitespace),
+            });
+        }
+    }
+    graphemes
+}
+
+fn pieces(graphemes: &[Grapheme]) -> Vec<Piece> {
+    let mut pieces: Vec<Piece> = Vec::new();
+    for g in graphemes {
+        match pieces.last_mut() {
+            Some(last) if last.span == g.span && last.end == g.start => last.end = g.end,
+            _ => pieces.push(Piece {
+                span: g.span,
+                start: g.start,
+                end: g.end,
+            }),
+        }
+    }
+    pieces
+}
+
+/// Splits a line into rows of at most `width` columns, breaking after the spaces when possible.
+/// The rows following the first one start with `indent` blank columns.
+pub fn wrap(line: &StyledLine, width: usize, indent: usize) -> Vec<Row> {
+    let width = width.max(1);
+    let indent = if indent < width { indent } else { 0 };
+    let graphemes = graphemes(line);
+
+    let mut rows: Vec<Row> = Vec::new();
+    let mut start = 0;
+
+    while start < graphemes.len() {
+        let (available, row_indent) = if rows.is_empty() {
+            (width, 0)
+        } else {
+            (width - indent, indent)
+        };
+
+        let mut used = 0;
+        // index following the last space of the row
+        let mut space_break = None;
+        let mut i = start;
+        while i < graphemes.len() {
+            let g = &graphemes[i];
+            if used + g.width > available && i > start {
+                break;
+            }
+            used += g.width;
+            i += 1;
+            if g.space {
+                space_break = Some(i);
+            }
+        }
+
+        let stop = match space_break {
+            Some(b) if i < graphemes.len() && !graphemes[i].space => b,
+            _ => i,
+        };
+
+        rows.push(Row {
+            indent: row_indent,
+            pieces: pieces(&graphemes[start..stop]),
+        });
+
+        // the spaces at the break are not carried over the next row
+        start = stop;
+        while start < graphemes.len() && graphemes[start].space {
+            start += 1;
+        }
+    }
+
+    if rows.is_empty() {
+        rows.push(Row::default());
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tui::style::{Color, Style};
+
+    fn texts(line: &StyledLine, rows: &[Row]) -> Vec<String> {
+        rows.iter()
+            .map(|row| {
+                let mut text = " ".repeat(row.indent);
+                for p in row.pieces.iter() {
+                    text.push_str(&line.spans[p.span].text[p.start..p.end]);
+                }
+                text
+            })
+            .collect()
+    }
+
+    #[test]
+    fn wraps_at_spaces() {
+        let line = StyledLine::styled("You see a small rat here.", Style::default());
+        assert_eq!(
+            texts(&line, &wrap(&line, 10, 0)),
+            vec!["You see a ", "small rat ", "here."]
+        );
+        assert_eq!(
+            texts(&line, &wrap(&line, 10, 2)),
+            vec!["You see a ", "  small ", "  rat ", "  here."]
+        );
+    }
+
+    #[test]
+    fn breaks_long_words_and_keeps_styles() {
+        let mut line = StyledLine::new();
+        line.push("abc", Style::default(), None);
+        line.push("defgh", Style::default().fg(Color::Red), None);
+
+        let rows = wrap(&line, 4, 0);
+        assert_eq!(texts(&line, &rows), vec!["abcd", "efgh"]);
+        assert_eq!(rows[0].pieces.len(), 2);
+        assert_eq!(rows[1].pieces[0], Piece { span: 1, start: 1, end: 5 });
+
+        assert_eq!(wrap(&StyledLine::new(), 4, 0), vec![Row::default()]);
+    }
+
+    #[test]
+    fn uses_display_width() {
+        let line = StyledLine::styled("日本語のテキスト", Style::default());
+        assert_eq!(
+            texts(&line, &wrap(&line, 5, 0)),
+            vec!["日本", "語の", "テキ", "スト"]
+        );
+    }
+}
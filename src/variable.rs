@@ -0,0 +1,149 @@
+use mudnet::msdp::MsdpVal;
+
+use crate::session::Session;
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn starts_name(name: &str) -> bool {
+    name.chars()
+        .next()
+        .map_or(false, |c| c.is_ascii_alphabetic() || c == '_')
+}
+
+/// Whether a user variable may be named so: a letter or `_` followed by letters, digits or `_`.
+/// The uppercase names are kept for the built-in variables, `$TIME`, `$DATE` and `$ROOM`,
+/// and the MSDP ones, e.g. `$HEALTH`.
+pub fn is_valid_name(name: &str) -> bool {
+    let uppercase = name.chars().all(|c| !c.is_ascii_lowercase());
+    starts_name(name) && name.chars().all(is_name_char) && !uppercase
+}
+
+impl Session {
+    /// The value of a built-in variable, an MSDP variable or a user variable
+    pub fn variable(&self, name: &str) -> Option<String> {
+        match name {
+            "TIME" => return Some(chrono::Local::now().format("%H:%M:%S").to_string()),
+            "DATE" => return Some(chrono::Local::now().format("%Y-%m-%d").to_string()),
+            "ROOM" => return self.room(),
+            _ => (),
+        }
+        match self.msdp.get(name) {
+            Some(MsdpVal::Value(value)) => Some(value.clone()),
+            _ => self.variables.get(name).cloned(),
+        }
+    }
+
+    /// Name of the current room, from MSDP or ATCP
+    fn room(&self) -> Option<String> {
+        match (self.msdp.get("ROOM_NAME"), self.msdp.get("ROOM")) {
+            (Some(MsdpVal::Value(name)), _) => Some(name.clone()),
+            (_, Some(MsdpVal::Table(entries))) => {
+                entries
+                    .iter()
+                    .find_map(|(key, value)| match (key.as_str(), value) {
+                        ("NAME", MsdpVal::Value(name)) => Some(name.clone()),
+                        _ => None,
+                    })
+            }
+            _ => self.atcp.get("Room.Brief").cloned(),
+        }
+    }
+}
+
+/// Replaces `$name` and `${name}` with the value found by `lookup`, `$$` giving a `$`.
+/// The unknown variables are left as typed.
+pub fn interpolate<F>(text: &str, lookup: F) -> String
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find('$') {
+        result.push_str(&rest[..i]);
+        let after = &rest[i + 1..];
+
+        if after.starts_with('$') {
+            result.push('$');
+            rest = &after[1..];
+            continue;
+        }
+        let (name, end) = if after.starts_with('{') {
+            match after.find('}') {
+                Some(close) => (&after[1..close], close + 1),
+                None => ("", 0),
+            }
+        } else {
+            let end = after.find(|c| !is_name_char(c)).unwrap_or(after.len());
+            (&after[..end], end)
+        };
+
+        let value = if starts_name(name) {
+            lookup(name)
+        } else {
+            None
+        };
+        match value {
+            Some(value) => {
+                result.push_str(&value);
+                rest = &after[end..];
+            }
+            _ => {
+                result.push('$');
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interpolates_the_known_variables() {
+        let lookup = |name: &str| match name {
+            "target" => Some(String::from("goblin")),
+            "n" => Some(String::from("2")),
+            _ => None,
+        };
+        assert_eq!(interpolate("kill $target", lookup), "kill goblin");
+        assert_eq!(interpolate("kill ${n}.$target!", lookup), "kill 2.goblin!");
+        assert_eq!(
+            interpolate("give $5 to $bob, $$target", lookup),
+            "give $5 to $bob, $target"
+        );
+        assert_eq!(interpolate("${target", lookup), "${target");
+    }
+
+    #[test]
+    fn reads_the_builtins_first() {
+        let mut session = Session::default();
+        session
+            .variables
+            .insert(String::from("target"), String::from("goblin"));
+        session
+            .msdp
+            .insert(String::from("HEALTH"), MsdpVal::Value(String::from("120")));
+        let room = vec![(
+            String::from("NAME"),
+            MsdpVal::Value(String::from("The Inn")),
+        )];
+        session
+            .msdp
+            .insert(String::from("ROOM"), MsdpVal::Table(room));
+
+        assert_eq!(session.variable("target"), Some(String::from("goblin")));
+        assert_eq!(session.variable("HEALTH"), Some(String::from("120")));
+        assert_eq!(session.variable("ROOM"), Some(String::from("The Inn")));
+        assert_eq!(session.variable("TIME").map(|t| t.len()), Some(8));
+        assert_eq!(session.variable("MANA"), None);
+
+        assert!(is_valid_name("target_2"));
+        assert!(!is_valid_name("HEALTH"));
+        assert!(!is_valid_name("2nd"));
+    }
+}
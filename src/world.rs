@@ -0,0 +1,208 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// File of the world directory keeping what is defined while playing
+pub const WORLD_FILE: &'static str = "world.toml";
+
+/// Aliases and other definitions of a world, saved as soon as they change
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorldData {
+    // the plain values come before the tables in the toml file,
+    // an empty list being a plain value `[]` it is not written
+    /// groups of triggers, gags, substitutions and highlights turned off
+    pub disabled_groups: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<AliasConfig>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub triggers: Vec<TriggerConfig>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub multi_triggers: Vec<MultiTriggerConfig>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub gags: Vec<GagConfig>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub substitutions: Vec<SubstitutionConfig>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub highlights: Vec<HighlightConfig>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub timers: Vec<TimerConfig>,
+    /// variables set with `:set`, or by the scripts
+    pub variables: BTreeMap<String, String>,
+    /// file the data is loaded from and saved to
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AliasConfig {
+    /// a word, or a regex between slashes
+    pub pattern: String,
+    pub body: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TriggerConfig {
+    /// a regex between slashes, or a text found anywhere in the line
+    pub pattern: String,
+    /// commands run when the line matches, with `%1`, `%*` or `%{name}` placeholders
+    pub action: String,
+    /// the triggers with the highest priority fire first
+    pub priority: i32,
+    /// removed once fired
+    pub once: bool,
+    pub group: String,
+    /// also matched against the prompts
+    pub prompt: bool,
+    /// defined by a script, not saved
+    #[serde(skip)]
+    pub scripted: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MultiTriggerConfig {
+    /// patterns of the successive lines, other lines being allowed between them
+    pub patterns: Vec<String>,
+    /// pattern of the closing line, the lines before it matching any of the patterns
+    pub until: String,
+    /// most lines a match spans, 0 for no limit, see `window()` when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window: Option<usize>,
+    /// commands run once the lines matched, the groups of all the lines being numbered in order
+    pub action: String,
+    pub group: String,
+}
+
+/// Most lines a match spans when the window is not set, without a closing pattern
+const DEFAULT_WINDOW: usize = 10;
+
+impl MultiTriggerConfig {
+    /// Most lines a match spans, 0 for no limit: unless set, 10 lines
+    /// or no limit when the match ends with a closing line
+    pub fn window(&self) -> usize {
+        match self.window {
+            Some(window) => window,
+            None if self.until.is_empty() => DEFAULT_WINDOW,
+            None => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GagConfig {
+    /// a regex between slashes, or a text found anywhere in the line
+    pub pattern: String,
+    pub group: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SubstitutionConfig {
+    /// a regex between slashes, or a text found anywhere in the line
+    pub pattern: String,
+    /// text replacing the matches, with `%1`, `%*` or `%{name}` placeholders
+    pub replacement: String,
+    pub group: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HighlightConfig {
+    /// a regex between slashes, or a text found anywhere in the line, unless words are given
+    pub pattern: String,
+    /// whole words to highlight, e.g. the names of the guild mates
+    pub words: Vec<String>,
+    /// `<fg>[/<bg>][+bold][+underline][+italic][+reverse]`, e.g. `black/yellow+bold`
+    pub style: String,
+    pub group: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimerConfig {
+    pub name: String,
+    /// period of the timer
+    pub seconds: f64,
+    pub command: String,
+    /// seconds before each run the warning command is run, 0 for no warning
+    pub warn: f64,
+    pub warn_command: String,
+    /// defined by a script, not saved
+    #[serde(skip)]
+    pub scripted: bool,
+}
+
+impl HighlightConfig {
+    /// The pattern, or the words separated by commas, naming the highlight
+    pub fn key(&self) -> String {
+        if self.words.is_empty() {
+            self.pattern.clone()
+        } else {
+            self.words.join(",")
+        }
+    }
+}
+
+impl WorldData {
+    /// Reads the data saved in the given file, if any
+    pub fn load(path: &Path) -> io::Result<WorldData> {
+        let mut data = match fs::read_to_string(path) {
+            Ok(content) => toml::from_str(&content)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => WorldData::default(),
+            Err(e) => return Err(e),
+        };
+        data.path = Some(PathBuf::from(path));
+        Ok(data)
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        if let Some(path) = &self.path {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let content =
+                toml::to_string(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            fs::write(path, content)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saves_any_mix_of_lists() {
+        let dir = crate::test_dir("world");
+        let path = dir.join(WORLD_FILE);
+        let mut world = WorldData::load(&path).unwrap();
+        world.aliases = vec![AliasConfig {
+            pattern: String::from("k"),
+            body: String::from("kill %1"),
+        }];
+        world.timers = vec![TimerConfig {
+            name: String::from("tick"),
+            seconds: 60.0,
+            command: String::from("score"),
+            ..TimerConfig::default()
+        }];
+        world.variables.insert(String::from("target"), String::from("goblin"));
+        world.save().unwrap();
+
+        let loaded = WorldData::load(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(loaded.aliases.len(), 1);
+        assert_eq!(loaded.aliases[0].body, "kill %1");
+        assert!(loaded.triggers.is_empty());
+        assert_eq!(loaded.timers[0].name, "tick");
+        assert_eq!(loaded.variables.get("target").map(String::as_str), Some("goblin"));
+    }
+}